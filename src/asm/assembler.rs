@@ -0,0 +1,301 @@
+use crate::asm::errors::*;
+use crate::parser::lexer::Lexer;
+use crate::parser::tokens::{BIn, Loc};
+use crate::vm::debug::DebugInfo;
+use crate::vm::instruction::Instruction;
+use crate::vm::vm::{Bytecode, Value};
+use std::collections::HashMap;
+
+// Часть файла, к которой относятся следующие строки
+#[derive(Clone, Copy)]
+enum Section {
+    Const,
+    Code,
+}
+
+// Слово строки: мнемоника, операнд, метка или константа
+struct Word {
+    text: String,
+    loc: Loc,
+}
+
+// Переход или вызов, адрес которого известен только после разбора всего файла
+struct Fixup {
+    offset: usize,
+    inst: Instruction,
+    label: String,
+    loc: Loc,
+}
+
+// Переводит текст на языке ассемблера в байткод. Формат описан в docs/asm.txt.
+// Команды записываются как есть, без проверок и оптимизаций, так что
+// можно получить любую последовательность команд VM
+pub struct Assembler {
+    file: String,
+    source: String,
+    code: Vec<u8>,
+    consts: Vec<Value>,
+    labels: HashMap<String, usize>,
+    fixups: Vec<Fixup>,
+    // Индексы констант в PUSH. Проверяются в конце, потому что
+    // секция .const может идти после кода
+    pushes: Vec<(u16, Loc)>,
+    debug: DebugInfo,
+    errors: Vec<AsmError>,
+}
+
+impl Assembler {
+    pub fn new(file: String, source: String) -> Self {
+        Self {
+            file,
+            source,
+            code: vec![],
+            consts: vec![],
+            labels: HashMap::new(),
+            fixups: vec![],
+            pushes: vec![],
+            debug: DebugInfo::new(),
+            errors: vec![],
+        }
+    }
+
+    // Как и лексер, сообщает сразу обо всех найденных ошибках
+    pub fn assemble(mut self) -> Result<Bytecode, Vec<AsmError>> {
+        let source = std::mem::take(&mut self.source);
+        let mut section = Section::Code;
+        for (number, line) in source.lines().enumerate() {
+            let words = self.words(number, line);
+            self.line(words, &mut section);
+        }
+        if u32::try_from(self.code.len()).is_err() {
+            return Err(vec![AsmError::CodeTooLong]);
+        }
+        self.resolve();
+        for (index, loc) in std::mem::take(&mut self.pushes) {
+            if index as usize >= self.consts.len() {
+                self.errors.push(AsmError::UnknownConst(loc));
+            }
+        }
+        if !self.errors.is_empty() {
+            return Err(self.errors);
+        }
+        Ok(Bytecode {
+            code: self.code,
+            consts: self.consts,
+            debug: Some(self.debug),
+        })
+    }
+
+    // Слова разделяются пробелами, строка в кавычках - одно слово.
+    // Комментарий начинается с ; и продолжается до конца строки
+    fn words(&self, number: usize, line: &str) -> Vec<Word> {
+        let chars: Vec<char> = line.chars().collect();
+        let mut words = vec![];
+        let mut i = 0;
+        while i < chars.len() {
+            let c = chars[i];
+            if c == ';' {
+                break;
+            }
+            if c.is_whitespace() {
+                i += 1;
+                continue;
+            }
+            let start = i;
+            if c == '"' {
+                i += 1;
+                while i < chars.len() && chars[i] != '"' {
+                    i += if chars[i] == '\\' { 2 } else { 1 };
+                }
+                i = (i + 1).min(chars.len());
+            } else {
+                while i < chars.len() && !chars[i].is_whitespace() && chars[i] != ';' {
+                    i += 1;
+                }
+            }
+            words.push(Word {
+                text: chars[start..i].iter().collect(),
+                loc: Loc::new(self.file.clone(), number, start).with_len(i - start),
+            });
+        }
+        words
+    }
+
+    fn line(&mut self, words: Vec<Word>, section: &mut Section) {
+        let mut words = words.into_iter();
+        let Some(first) = words.next() else {
+            return;
+        };
+        if first.text.starts_with('.') {
+            match first.text.as_str() {
+                ".const" => *section = Section::Const,
+                ".code" => *section = Section::Code,
+                _ => self.errors.push(AsmError::UnknownDirective(first.loc)),
+            }
+        } else {
+            match section {
+                Section::Const => match Self::parse_const(&first.text) {
+                    Some(value) => self.consts.push(value),
+                    None => self.errors.push(AsmError::InvalidConst(first.loc)),
+                },
+                Section::Code => self.instruction(first, &mut words),
+            }
+        }
+        if let Some(extra) = words.next() {
+            self.errors.push(AsmError::ExtraOperand(extra.loc));
+        }
+    }
+
+    // [метка:] [МНЕМОНИКА [операнд]]
+    fn instruction(&mut self, mut first: Word, words: &mut impl Iterator<Item = Word>) {
+        if let Some(name) = first.text.strip_suffix(':') {
+            self.define_label(name, &first.loc);
+            match words.next() {
+                Some(word) => first = word,
+                None => return,
+            }
+        }
+        let Some(inst) = Instruction::from_mnemonic(&first.text) else {
+            self.errors.push(AsmError::UnknownMnemonic(first.loc));
+            // Об операндах неизвестной команды не сообщается
+            words.for_each(drop);
+            return;
+        };
+        let offset = self.code.len();
+        self.debug.add_line(offset, &first.loc);
+        self.code.push(inst as u8);
+        // Место под операнд занимается и при ошибке, чтобы адреса
+        // следующих меток остались верными
+        let size = inst.operands_size();
+        if size == 0 {
+            return;
+        }
+        let operand = match words.next() {
+            Some(word) => self.operand(offset, inst, word),
+            None => {
+                self.errors.push(AsmError::MissingOperand(first.loc));
+                0
+            }
+        };
+        self.code
+            .extend_from_slice(&u32::to_le_bytes(operand)[..size]);
+    }
+
+    // Значение операнда. Для переходов и вызовов записывается 0,
+    // настоящее значение подставляется в resolve
+    fn operand(&mut self, offset: usize, inst: Instruction, word: Word) -> u32 {
+        let value = match inst {
+            Instruction::Jmp | Instruction::Jf | Instruction::Jback | Instruction::Call => {
+                self.fixups.push(Fixup {
+                    offset,
+                    inst,
+                    label: word.text,
+                    loc: word.loc,
+                });
+                return 0;
+            }
+            Instruction::Push => word.text.parse::<u16>().ok().map(|index| {
+                self.pushes.push((index, word.loc.clone()));
+                index as u32
+            }),
+            // Встроенная функция задается именем или номером
+            Instruction::Bin => (0..)
+                .map_while(BIn::from_number)
+                .find(|bin| bin.name() == word.text)
+                .map(|bin| bin.number())
+                .or_else(|| word.text.parse::<u16>().ok())
+                .map(u32::from),
+            Instruction::Hcall => word.text.parse::<u16>().ok().map(u32::from),
+            _ => word.text.parse::<u32>().ok(),
+        };
+        value.unwrap_or_else(|| {
+            self.errors.push(AsmError::InvalidOperand(word.loc));
+            0
+        })
+    }
+
+    fn define_label(&mut self, name: &str, loc: &Loc) {
+        let mut chars = name.chars();
+        let valid = chars.next().is_some_and(|c| c.is_alphabetic() || c == '_')
+            && chars.all(|c| c.is_alphanumeric() || c == '_' || c == '.');
+        if !valid {
+            self.errors.push(AsmError::InvalidLabel(loc.clone()));
+        } else if self.labels.contains_key(name) {
+            self.errors.push(AsmError::DuplicateLabel(loc.clone()));
+        } else {
+            self.labels.insert(name.to_string(), self.code.len());
+        }
+    }
+
+    // Подставляет адреса меток в переходы и вызовы. Метка вызова
+    // становится именем функции в отладочной информации
+    fn resolve(&mut self) {
+        for Fixup {
+            offset,
+            inst,
+            label,
+            loc,
+        } in std::mem::take(&mut self.fixups)
+        {
+            let Some(&target) = self.labels.get(&label) else {
+                self.errors.push(AsmError::UnknownLabel(loc));
+                continue;
+            };
+            let operand = match inst {
+                Instruction::Call => {
+                    if self.debug.function(target).is_none() {
+                        self.debug.add_function(target, &label);
+                    }
+                    u32::to_le_bytes(target as u32).to_vec()
+                }
+                _ => {
+                    let distance = match inst {
+                        Instruction::Jback => offset.checked_sub(target),
+                        _ => target.checked_sub(offset),
+                    };
+                    let Some(distance) = distance else {
+                        self.errors.push(AsmError::WrongJumpDirection(loc));
+                        continue;
+                    };
+                    let Ok(distance) = u16::try_from(distance) else {
+                        self.errors.push(AsmError::JumpTooLong(loc));
+                        continue;
+                    };
+                    u16::to_le_bytes(distance).to_vec()
+                }
+            };
+            self.code[(offset + 1)..(offset + 1 + operand.len())].copy_from_slice(&operand);
+        }
+    }
+
+    // Константа записывается так же, как литерал в программе на Hammer.
+    // Бесконечность и NaN литералом не записать, поэтому они не принимаются
+    fn parse_const(text: &str) -> Option<Value> {
+        match text {
+            "true" => Some(Value::Bool(true)),
+            "false" => Some(Value::Bool(false)),
+            "nil" => Some(Value::Nil),
+            _ if text.starts_with('"') => {
+                Self::parse_string(&text[1..]).map(|s| Value::Str(s.into()))
+            }
+            _ => text
+                .parse::<f64>()
+                .ok()
+                .filter(|n| n.is_finite())
+                .map(Value::Number),
+        }
+    }
+
+    // Открывающая кавычка уже пропущена. Закрывающая должна быть последним символом
+    fn parse_string(text: &str) -> Option<String> {
+        let mut chars = text.chars().peekable();
+        let mut s = String::new();
+        loop {
+            match chars.next()? {
+                '"' => return chars.next().is_none().then_some(s),
+                '\\' => s.push(Lexer::decode_escape(&mut chars).0?),
+                c => s.push(c),
+            }
+        }
+    }
+}
@@ -0,0 +1,115 @@
+use crate::diagnostics::{Diagnostic, ToDiagnostic};
+use crate::locale::tr;
+use crate::parser::tokens::Loc;
+use std::fmt;
+
+#[derive(Debug)]
+pub enum AsmError {
+    UnknownDirective(Loc),
+    UnknownMnemonic(Loc),
+    MissingOperand(Loc),
+    ExtraOperand(Loc),
+    InvalidOperand(Loc),
+    InvalidConst(Loc),
+    UnknownConst(Loc),
+    InvalidLabel(Loc),
+    DuplicateLabel(Loc),
+    UnknownLabel(Loc),
+    WrongJumpDirection(Loc),
+    JumpTooLong(Loc),
+    CodeTooLong,
+}
+
+impl AsmError {
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::UnknownDirective(_) => "H0701",
+            Self::UnknownMnemonic(_) => "H0702",
+            Self::MissingOperand(_) => "H0703",
+            Self::ExtraOperand(_) => "H0704",
+            Self::InvalidOperand(_) => "H0705",
+            Self::InvalidConst(_) => "H0706",
+            Self::UnknownConst(_) => "H0707",
+            Self::InvalidLabel(_) => "H0708",
+            Self::DuplicateLabel(_) => "H0709",
+            Self::UnknownLabel(_) => "H0710",
+            Self::WrongJumpDirection(_) => "H0711",
+            Self::JumpTooLong(_) => "H0712",
+            Self::CodeTooLong => "H0713",
+        }
+    }
+}
+
+impl ToDiagnostic for AsmError {
+    fn diagnostic(&self) -> Diagnostic {
+        let diagnostic = match self {
+            Self::UnknownDirective(loc) => {
+                Diagnostic::new(tr!("неизвестная директива", "unknown directive"))
+                    .at(loc)
+                    .with_note(tr!(
+                        "поддерживаются .const и .code",
+                        "supported directives are .const and .code"
+                    ))
+            }
+            Self::UnknownMnemonic(loc) => {
+                Diagnostic::new(tr!("неизвестная команда", "unknown instruction")).at(loc)
+            }
+            Self::MissingOperand(loc) => {
+                Diagnostic::new(tr!("у команды нет операнда", "instruction has no operand")).at(loc)
+            }
+            Self::ExtraOperand(loc) => {
+                Diagnostic::new(tr!("лишний операнд", "unexpected operand")).at(loc)
+            }
+            Self::InvalidOperand(loc) => {
+                Diagnostic::new(tr!("неверный операнд", "invalid operand")).at(loc)
+            }
+            Self::InvalidConst(loc) => {
+                Diagnostic::new(tr!("неверная константа", "invalid constant"))
+                    .at(loc)
+                    .with_note(tr!(
+                        "константа - число, строка в кавычках, true, false или nil",
+                        "a constant is a number, a quoted string, true, false or nil"
+                    ))
+            }
+            Self::UnknownConst(loc) => Diagnostic::new(tr!(
+                "нет константы с таким индексом",
+                "no constant with this index"
+            ))
+            .at(loc),
+            Self::InvalidLabel(loc) => {
+                Diagnostic::new(tr!("неверное имя метки", "invalid label name")).at(loc)
+            }
+            Self::DuplicateLabel(loc) => {
+                Diagnostic::new(tr!("метка уже объявлена", "label is already defined")).at(loc)
+            }
+            Self::UnknownLabel(loc) => {
+                Diagnostic::new(tr!("неизвестная метка", "unknown label")).at(loc)
+            }
+            Self::WrongJumpDirection(loc) => Diagnostic::new(tr!(
+                "переход в неверном направлении",
+                "jump in the wrong direction"
+            ))
+            .at(loc)
+            .with_note(tr!(
+                "JMP и JF переходят только вперед, JBACK - только назад",
+                "JMP and JF only jump forward, JBACK only jumps backward"
+            )),
+            Self::JumpTooLong(loc) => Diagnostic::new(tr!(
+                "слишком длинный переход: расстояние превышает 65535 байт",
+                "jump is too long: the distance exceeds 65535 bytes"
+            ))
+            .at(loc),
+            Self::CodeTooLong => Diagnostic::new(tr!(
+                "размер кода превышает 4294967295 байт",
+                "code size exceeds 4294967295 bytes"
+            )),
+        };
+        diagnostic.with_code(self.code())
+    }
+}
+
+impl fmt::Display for AsmError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.diagnostic())
+    }
+}
@@ -1,21 +1,69 @@
+use crate::diagnostics::{Diagnostic, ToDiagnostic};
+use crate::locale::tr;
 use std::fmt;
+use std::io;
 
+#[derive(Debug)]
 pub enum CliError {
     UnknownCommand(String),
     NoCommand,
     NoInputFile,
     IncorrectParam(String, String),
+    UnknownLang(String),
+    UnknownMessageFormat(String),
+    UnknownEmit(String),
+    // Входной файл и ошибка чтения
+    ReadFailed(String, io::Error),
 }
 
-impl fmt::Display for CliError {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+impl CliError {
+    pub fn code(&self) -> &'static str {
         match self {
-            Self::UnknownCommand(c) => write!(f, "Ошибка: неизвестная команда {c}"),
-            Self::NoCommand => write!(f, "Ошибка: не задана команда"),
-            Self::NoInputFile => write!(f, "Ошибка: не задан входной файл"),
-            Self::IncorrectParam(c, param) => {
-                write!(f, "Ошибка: неверный параметр для команды {c}: {param}")
-            }
+            Self::UnknownCommand(_) => "H0001",
+            Self::NoCommand => "H0002",
+            Self::NoInputFile => "H0003",
+            Self::IncorrectParam(..) => "H0004",
+            Self::UnknownLang(_) => "H0005",
+            Self::UnknownMessageFormat(_) => "H0006",
+            Self::UnknownEmit(_) => "H0007",
+            Self::ReadFailed(..) => "H0008",
         }
     }
 }
+
+impl ToDiagnostic for CliError {
+    fn diagnostic(&self) -> Diagnostic {
+        Diagnostic::new(self.to_string()).with_code(self.code())
+    }
+}
+
+impl fmt::Display for CliError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let message = match self {
+            Self::UnknownCommand(c) => tr!("неизвестная команда {c}", "unknown command {c}"),
+            Self::NoCommand => tr!("не задана команда", "no command given"),
+            Self::NoInputFile => tr!("не задан входной файл", "no input file given"),
+            Self::IncorrectParam(c, param) => tr!(
+                "неверный параметр для команды {c}: {param}",
+                "invalid parameter for command {c}: {param}"
+            ),
+            Self::UnknownLang(lang) => tr!(
+                "неизвестный язык \"{lang}\", поддерживаются ru и en",
+                "unknown language \"{lang}\", supported languages are ru and en"
+            ),
+            Self::UnknownMessageFormat(format) => tr!(
+                "неизвестный формат сообщений \"{format}\", поддерживаются human и json",
+                "unknown message format \"{format}\", supported formats are human and json"
+            ),
+            Self::UnknownEmit(emit) => tr!(
+                "неизвестный вид результата \"{emit}\", поддерживаются bytecode и ir",
+                "unknown output kind \"{emit}\", supported kinds are bytecode and ir"
+            ),
+            Self::ReadFailed(file, e) => tr!(
+                "не удалось прочитать файл {file}: {e}",
+                "cannot read file {file}: {e}"
+            ),
+        };
+        write!(f, "{message}")
+    }
+}
@@ -1,22 +1,34 @@
 pub mod errors;
+mod repl;
 use crate::{
-    compile::{compiler::Compiler, errors::CompileError},
-    error::HammerError,
-    parser::{
-        ast::{Ast, AstBuilder},
-        lexer::Lexer,
+    asm::assembler::Assembler,
+    compile::{
+        compiler::Compiler, encoder::Encoder, errors::CompileError, ir::Ir, optimizer::Optimizer,
+    },
+    diagnostics::{Diagnostic, MessageFormat, Renderer},
+    error::{ErrorKind, HammerError},
+    locale::{tr, Lang},
+    parser::{self, ast::Ast},
+    vm::{
+        disasm::Disassembler,
+        vm::{Bytecode, VM},
     },
-    vm::vm::VM,
 };
 use errors::*;
 use regex::Regex;
+use repl::Repl;
+use std::env::{self, Args};
 use std::fs;
-use std::{env::Args, io::Read, rc::Rc};
+use std::process::ExitCode;
+
+const MAX_REPORTED_ERRORS: usize = 20;
 
 enum Command {
     Compile,
+    Asm,
     Run(RunType),
     Inspect,
+    Repl,
     Help,
 }
 
@@ -25,27 +37,91 @@ enum RunType {
     Bytecode,
 }
 
+// Что записывает команда compile
+#[derive(Clone, Copy, PartialEq)]
+enum Emit {
+    Bytecode,
+    // Листинг промежуточного представления в stdout
+    Ir,
+}
+
+impl Emit {
+    fn parse(name: &str) -> Option<Self> {
+        match name {
+            "bytecode" => Some(Self::Bytecode),
+            "ir" => Some(Self::Ir),
+            _ => None,
+        }
+    }
+}
+
+// Параметры, которые могут стоять в любом месте командной строки
+struct Options {
+    message_format: MessageFormat,
+    optimize: bool,
+    emit: Option<Emit>,
+}
+
 pub struct Cli {
     command: Command,
     in_file: Option<String>,
     out_file: Option<String>,
+    message_format: MessageFormat,
+    optimize: bool,
+    emit: Emit,
 }
 
 impl Cli {
-    pub fn usage() {
-        println!("hammer <КОМАНДА> [ПАРАМЕТРЫ] ВХОДНОЙ_ФАЙЛ [ВЫХОДНОЙ_ФАЙЛ]");
-        println!("Команды");
-        println!("  compile <in> [out]      скомпилировать файл <in> (в файл [out], если задан)");
-        println!("  run <in>                скомпилировать и запустить файл");
-        println!("    run -b <in>           запустить файл с байткодом");
-        println!("  inspect <in>            исследовать файл с байткодом (команды в байткоде + список констант)");
-        println!("  help                    показать эту справку")
+    pub fn usage() -> String {
+        tr!(
+            "hammer <КОМАНДА> [ПАРАМЕТРЫ] ВХОДНОЙ_ФАЙЛ [ВЫХОДНОЙ_ФАЙЛ]
+Команды
+  compile <in> [out]      скомпилировать файл <in> (в файл [out], если задан)
+  asm <in> [out]          собрать байткод из файла на языке ассемблера
+  run <in>                скомпилировать и запустить файл
+    run -b <in>           запустить файл с байткодом
+  inspect <in>            исследовать файл с байткодом (команды в байткоде + список констант)
+  repl                    запустить интерактивный режим
+  help                    показать эту справку
+Параметры
+  --lang <ru|en>          язык сообщений (по умолчанию берется из HAMMER_LANG или LANG,
+                          для нерусской локали - английский)
+  --message-format <fmt>  вид сообщений об ошибках: human (по умолчанию) или json
+  -O                      оптимизировать байткод (для compile и run)
+  --emit <bytecode|ir>    что выводит compile: файл с байткодом (по умолчанию)
+                          или промежуточное представление в stdout
+",
+            "hammer <COMMAND> [OPTIONS] INPUT_FILE [OUTPUT_FILE]
+Commands
+  compile <in> [out]      compile file <in> (into file [out], if given)
+  asm <in> [out]          assemble bytecode from an assembly language file
+  run <in>                compile and run a file
+    run -b <in>           run a bytecode file
+  inspect <in>            inspect a bytecode file (instructions and constant table)
+  repl                    start interactive mode
+  help                    show this help
+Options
+  --lang <ru|en>          message language (taken from HAMMER_LANG or LANG by default,
+                          English for non-Russian locales)
+  --message-format <fmt>  error message format: human (default) or json
+  -O                      optimize bytecode (for compile and run)
+  --emit <bytecode|ir>    what compile outputs: a bytecode file (default)
+                          or the intermediate representation to stdout
+"
+        )
     }
 
     pub fn new(args: &mut Args) -> Result<Self, CliError> {
         let _program = args
             .next()
             .expect("Невозможная ситуация: нет первого аргумента командной строки");
+        let (options, args) = Self::take_options(args.collect())?;
+        let Options {
+            message_format,
+            optimize,
+            emit,
+        } = options;
+        let mut args = args.into_iter();
         let command = args.next().ok_or(CliError::NoCommand)?;
         let next_arg = args.next();
         let re = Regex::new(r"\..*$").expect("\\..*$ является верным регулярным выражением");
@@ -58,12 +134,15 @@ impl Cli {
                 command: Command::Run(RunType::Bytecode),
                 in_file: Some(in_file.clone()),
                 out_file: None,
+                message_format,
+                optimize,
+                emit: Emit::Bytecode,
             }
         } else {
             let in_file = match next_arg {
                 Some(f) => Some(f),
                 None => {
-                    if command == "help" {
+                    if command == "help" || command == "repl" {
                         None
                     } else {
                         return Err(CliError::NoInputFile);
@@ -75,12 +154,13 @@ impl Cli {
             match command.as_str() {
                 "run" => {
                     com_type = Command::Run(RunType::Source);
-                    out_file = Some(args.next().unwrap_or(String::from(
-                        re.replace(in_file.clone().unwrap().as_str(), ""),
-                    )));
+                    out_file = None;
                 }
-                "compile" => {
-                    com_type = Command::Compile;
+                "compile" | "asm" => {
+                    com_type = match command.as_str() {
+                        "compile" => Command::Compile,
+                        _ => Command::Asm,
+                    };
                     out_file = Some(args.next().unwrap_or(String::from(
                         re.replace(in_file.clone().unwrap().as_str(), ""),
                     )));
@@ -89,6 +169,10 @@ impl Cli {
                     com_type = Command::Inspect;
                     out_file = None;
                 }
+                "repl" => {
+                    com_type = Command::Repl;
+                    out_file = None;
+                }
                 "help" => {
                     com_type = Command::Help;
                     out_file = None;
@@ -99,127 +183,261 @@ impl Cli {
                 command: com_type,
                 in_file,
                 out_file,
+                message_format,
+                optimize,
+                emit: emit.unwrap_or(Emit::Bytecode),
             }
         };
+        // Оптимизировать можно только байткод, который компилируется из исходного кода
+        if cli.optimize
+            && !matches!(
+                cli.command,
+                Command::Compile | Command::Run(RunType::Source)
+            )
+        {
+            return Err(CliError::IncorrectParam(command, String::from("-O")));
+        }
+        if emit.is_some() && !matches!(cli.command, Command::Compile) {
+            return Err(CliError::IncorrectParam(command, String::from("--emit")));
+        }
+        // Оптимизатор работает с байткодом, промежуточное представление выводится до него
+        if cli.optimize && cli.emit == Emit::Ir {
+            return Err(CliError::IncorrectParam(command, String::from("-O")));
+        }
         Ok(cli)
     }
 
-    pub fn run(&self) -> Result<(), HammerError> {
+    // Параметры --lang, --message-format, --emit и -O могут стоять в любом месте,
+    // значение задается через пробел или через =.
+    // Возвращает параметры и остальные аргументы
+    fn take_options(args: Vec<String>) -> Result<(Options, Vec<String>), CliError> {
+        let mut options = Options {
+            message_format: MessageFormat::Human,
+            optimize: false,
+            emit: None,
+        };
+        let mut rest = vec![];
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            if let Some(name) = Self::option_value(&arg, "--lang", &mut args) {
+                Lang::parse(&name).ok_or(CliError::UnknownLang(name))?.set();
+            } else if let Some(name) = Self::option_value(&arg, "--message-format", &mut args) {
+                options.message_format =
+                    MessageFormat::parse(&name).ok_or(CliError::UnknownMessageFormat(name))?;
+            } else if let Some(name) = Self::option_value(&arg, "--emit", &mut args) {
+                options.emit = Some(Emit::parse(&name).ok_or(CliError::UnknownEmit(name))?);
+            } else if arg == "-O" {
+                options.optimize = true;
+            } else {
+                rest.push(arg);
+            }
+        }
+        Ok((options, rest))
+    }
+
+    // Формат сообщений из командной строки, даже если в ней есть ошибка.
+    // Неизвестный формат заменяется на human
+    fn requested_message_format(mut args: impl Iterator<Item = String>) -> MessageFormat {
+        let mut format = MessageFormat::Human;
+        while let Some(arg) = args.next() {
+            if let Some(name) = Self::option_value(&arg, "--message-format", &mut args) {
+                format = MessageFormat::parse(&name).unwrap_or(MessageFormat::Human);
+            }
+        }
+        format
+    }
+
+    fn option_value(
+        arg: &str,
+        option: &str,
+        args: &mut impl Iterator<Item = String>,
+    ) -> Option<String> {
+        let value = arg.strip_prefix(option)?;
+        if value.is_empty() {
+            return Some(args.next().unwrap_or_default());
+        }
+        value.strip_prefix('=').map(String::from)
+    }
+
+    // Возвращает код завершения, заданный программой через exit (по умолчанию 0)
+    pub fn run(&self) -> Result<u8, HammerError> {
         match &self.command {
+            Command::Compile if self.emit == Emit::Ir => {
+                print!("{}", self.lower()?);
+                Ok(0)
+            }
             Command::Compile => {
-                let mut input_file = match fs::OpenOptions::new()
-                    .read(true)
-                    .open(self.in_file.clone().unwrap())
-                {
-                    Ok(f) => f,
-                    Err(e) => {
-                        return Err(HammerError::Compile(CompileError::FileError(
-                            self.in_file.clone().unwrap(),
-                            e,
-                        )))
-                    }
-                };
-                self.compile(&mut input_file)
+                let bytecode = self.compile()?;
+                let file = self.write_output(bytecode)?;
+                println!(
+                    "{}",
+                    tr!(
+                        "Компиляция прошла успешно: {file}",
+                        "Compilation succeeded: {file}"
+                    )
+                );
+                Ok(0)
             }
-            Command::Run(rt) => {
-                let mut input_file = match fs::OpenOptions::new()
-                    .read(true)
-                    .open(self.in_file.clone().unwrap())
-                {
-                    Ok(f) => f,
-                    Err(e) => {
-                        return Err(HammerError::Compile(CompileError::FileError(
-                            self.in_file.clone().unwrap(),
-                            e,
-                        )))
-                    }
-                };
-                match rt {
-                    RunType::Bytecode => self.interp(true),
-                    RunType::Source => {
-                        self.compile(&mut input_file)?;
-                        self.interp(false)
-                    }
-                }
+            Command::Asm => {
+                let bytecode = self.assemble()?;
+                let file = self.write_output(bytecode)?;
+                println!(
+                    "{}",
+                    tr!(
+                        "Сборка прошла успешно: {file}",
+                        "Assembly succeeded: {file}"
+                    )
+                );
+                Ok(0)
+            }
+            Command::Run(RunType::Source) => {
+                let bytecode = self.compile()?;
+                Self::interp(VM::from_bytecode(bytecode))
+            }
+            Command::Run(RunType::Bytecode) => {
+                let bytecode = self.read_input()?;
+                let vm = VM::new(bytecode).map_err(ErrorKind::Bytecode)?;
+                Self::interp(vm)
             }
             Command::Inspect => {
-                todo!("Исследование файла байткода");
+                self.inspect()?;
+                Ok(0)
             }
+            Command::Repl => Ok(Repl::new().run()),
             Command::Help => {
-                Self::usage();
-                Ok(())
+                print!("{}", Self::usage());
+                Ok(0)
             }
         }
     }
 
-    fn compile(&self, input_file: &mut fs::File) -> Result<(), HammerError> {
-        let mut program: String = String::new();
-        input_file.read_to_string(&mut program);
-        let mut lexer = Lexer::new(self.in_file.clone().unwrap(), program);
-        match lexer.lex() {
-            Ok(()) => {}
-            Err(e) => {
-                return Err(HammerError::Lex(e));
+    pub fn message_format(&self) -> MessageFormat {
+        self.message_format
+    }
+
+    // Ошибки выводятся в stderr, чтобы не смешиваться с выводом программы.
+    // Если ошибок больше MAX_REPORTED_ERRORS, выводятся только первые из них.
+    // В формате JSON выводятся все ошибки
+    pub fn report(format: MessageFormat, diagnostics: &[Diagnostic]) {
+        if format == MessageFormat::Json {
+            for diagnostic in diagnostics {
+                eprintln!("{}", diagnostic.to_json());
             }
+            return;
+        }
+        let mut renderer = Renderer::for_stderr();
+        for diagnostic in diagnostics.iter().take(MAX_REPORTED_ERRORS) {
+            eprintln!("{}", renderer.render(diagnostic));
+        }
+        let count = diagnostics.len();
+        if count > MAX_REPORTED_ERRORS {
+            let rest = count - MAX_REPORTED_ERRORS;
+            eprintln!(
+                "{}",
+                tr!("... и еще ошибок: {rest}", "... and {rest} more errors")
+            );
         }
+        if count > 1 {
+            eprintln!(
+                "{}",
+                tr!("всего ошибок: {count}", "{count} errors in total")
+            );
+        }
+    }
 
-        let mut ast_builder = AstBuilder::new(lexer.tokens().to_vec());
-        match ast_builder.parse() {
-            Ok(()) => {}
-            Err(e) => {
-                return Err(HammerError::Parse(e));
-            }
-        };
+    fn read_input(&self) -> Result<Vec<u8>, HammerError> {
+        let path = self
+            .in_file
+            .clone()
+            .expect("Для этой команды значение in_file всегда задано");
+        let bytes = fs::read(&path).map_err(|e| ErrorKind::Cli(CliError::ReadFailed(path, e)))?;
+        Ok(bytes)
+    }
 
-        let Ast { tree, variables } = ast_builder.ast();
-        let mut compiler = match Compiler::new(
-            self.out_file
-                .clone()
-                .expect("При компиляции значение out_file всегда задано"),
-        ) {
-            Ok(c) => c,
-            Err(e) => {
-                return Err(HammerError::Compile(e));
-            }
-        };
-        match compiler.compile(tree, variables) {
-            Ok(()) => {
-                let file = self
-                    .out_file
-                    .clone()
-                    .expect("При компиляции значение out_file всегда задано");
-                println!("Компиляция прошла успешно: {file}");
-            }
-            Err(e) => return Err(HammerError::Compile(e)),
+    // Возвращает имя записанного файла
+    fn write_output(&self, bytecode: Bytecode) -> Result<String, HammerError> {
+        let file = self
+            .out_file
+            .clone()
+            .expect("При компиляции значение out_file всегда задано");
+        let bytes = bytecode.to_bytes().map_err(ErrorKind::Bytecode)?;
+        fs::write(&file, bytes)
+            .map_err(|e| ErrorKind::Compile(CompileError::FileError(file.clone(), e)))?;
+        Ok(file)
+    }
+
+    fn assemble(&self) -> Result<Bytecode, HammerError> {
+        let path = self
+            .in_file
+            .clone()
+            .expect("При сборке значение in_file всегда задано");
+        let source = fs::read_to_string(&path)
+            .map_err(|e| ErrorKind::Cli(CliError::ReadFailed(path.clone(), e)))?;
+        let bytecode = Assembler::new(path, source)
+            .assemble()
+            .map_err(ErrorKind::Asm)?;
+        Ok(bytecode)
+    }
+
+    fn compile(&self) -> Result<Bytecode, HammerError> {
+        let ir = self.lower()?;
+        let bytecode = Encoder::new(&ir).encode().map_err(ErrorKind::Compile)?;
+        if self.optimize {
+            return Ok(Optimizer::new(bytecode).optimize());
         }
+        Ok(bytecode)
+    }
+
+    fn lower(&self) -> Result<Ir, HammerError> {
+        let path = self
+            .in_file
+            .clone()
+            .expect("При компиляции значение in_file всегда задано");
+        let program = fs::read_to_string(&path)
+            .map_err(|e| ErrorKind::Cli(CliError::ReadFailed(path.clone(), e)))?;
+        let Ast { tree, variables } = parser::parse(path, program, [])?;
+        let ir = Compiler::new()
+            .lower(tree, variables)
+            .map_err(ErrorKind::Compile)?;
+        Ok(ir)
+    }
+
+    fn inspect(&self) -> Result<(), HammerError> {
+        let bytecode = self.read_input()?;
+        let listing = Disassembler::new(bytecode)
+            .and_then(|d| d.disassemble())
+            .map_err(ErrorKind::Bytecode)?;
+        print!("{listing}");
         Ok(())
     }
 
-    fn interp(&self, b: bool) -> Result<(), HammerError> {
-        let path = if b {
-            self
-                .in_file
-                .clone()
-                .expect("При запуске с -b значение in_file всегда задано")
-        } else {
-            self
-                .out_file
-                .clone()
-                .expect("При запуске значение out_file всегда задано")
-        };
-        let mut file = match fs::OpenOptions::new().read(true).open(path.clone()) {
-            Ok(f) => f,
-            Err(e) => return Err(HammerError::Compile(CompileError::FileError(path, e))),
-        };
-        let mut bytecode: Vec<u8> = vec![];
-        file.read_to_end(&mut bytecode);
-        let mut vm = match VM::new(bytecode) {
-            Ok(v) => v,
-            Err(e) => return Err(HammerError::Bytecode(e)),
-        };
-        match vm.run() {
-            Ok(()) => Ok(()),
-            Err(e) => Err(HammerError::Interp(e)),
+    fn interp(mut vm: VM) -> Result<u8, HammerError> {
+        vm.run().map_err(ErrorKind::Interp)?;
+        Ok(vm.exit_code().unwrap_or(0))
+    }
+}
+
+// Точка входа исполняемого файла hammer
+pub fn main() -> ExitCode {
+    let mut args = env::args();
+    let cli = match Cli::new(&mut args) {
+        Ok(c) => c,
+        Err(e) => {
+            let e = HammerError::from(ErrorKind::Cli(e));
+            let format = Cli::requested_message_format(env::args().skip(1));
+            Cli::report(format, &e.diagnostics());
+            // В формате json каждая строка stderr - одно сообщение
+            if format == MessageFormat::Human {
+                eprint!("{}", Cli::usage());
+            }
+            return ExitCode::from(e.exit_code());
+        }
+    };
+    match cli.run() {
+        Ok(code) => ExitCode::from(code),
+        Err(e) => {
+            Cli::report(cli.message_format(), &e.diagnostics());
+            ExitCode::from(e.exit_code())
         }
     }
 }
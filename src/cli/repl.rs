@@ -0,0 +1,153 @@
+use crate::{
+    compile::compiler::Compiler,
+    diagnostics::Renderer,
+    error::{ErrorKind, HammerError},
+    locale::tr,
+    parser::{
+        ast::AstBuilder,
+        errors::LexError,
+        lexer::Lexer,
+        tokens::{Token, TokenType},
+    },
+    vm::vm::VM,
+};
+use std::io::{self, Write};
+
+const REPL_FILE: &str = "<repl>";
+
+// Состояние сеанса сохраняется между строками: переменные и функции,
+// объявленные раньше, видны в следующих строках
+pub struct Repl {
+    ast_builder: AstBuilder,
+    compiler: Compiler,
+    vm: VM,
+}
+
+impl Repl {
+    pub fn new() -> Self {
+        let compiler = Compiler::new();
+        let Ok(bytecode) = compiler.bytecode() else {
+            panic!("Невозможная ситуация: пустая программа не переводится в байткод");
+        };
+        let vm = VM::from_bytecode(bytecode);
+        Self {
+            ast_builder: AstBuilder::new(vec![]),
+            compiler,
+            vm,
+        }
+    }
+
+    // Сеанс заканчивается с концом ввода или вызовом exit.
+    // Возвращает код завершения
+    pub fn run(&mut self) -> u8 {
+        println!(
+            "{}",
+            tr!(
+                "Hammer REPL. Для выхода нажмите Ctrl+D",
+                "Hammer REPL. Press Ctrl+D to exit"
+            )
+        );
+        let mut source = String::new();
+        loop {
+            print!("{}", if source.is_empty() { "> " } else { "... " });
+            io::stdout().flush();
+            let mut line = String::new();
+            // stdin не блокируется на весь сеанс, чтобы из него могла читать и программа
+            match io::stdin().read_line(&mut line) {
+                Ok(0) | Err(_) => break,
+                Ok(_) => source.push_str(&line),
+            }
+            if Self::unfinished(&source) {
+                continue;
+            }
+            let input = std::mem::take(&mut source);
+            if input.trim().is_empty() {
+                continue;
+            }
+            if let Err(e) = self.eval(input.clone()) {
+                // Места ошибок указывают на только что введенный текст
+                let mut renderer = Renderer::for_stderr();
+                renderer.add_source(REPL_FILE, &input);
+                for diagnostic in e.diagnostics() {
+                    eprintln!("{}", renderer.render(&diagnostic));
+                }
+            }
+            if let Some(code) = self.vm.exit_code() {
+                return code;
+            }
+        }
+        println!();
+        0
+    }
+
+    // Ввод продолжается на следующей строке, пока не закрыты
+    // все фигурные скобки и строковые литералы
+    fn unfinished(source: &str) -> bool {
+        let mut lexer = Lexer::new(String::from(REPL_FILE), source.to_string());
+        match lexer.lex() {
+            Ok(()) => {
+                let depth = lexer
+                    .tokens()
+                    .iter()
+                    .fold(0, |depth, token| match token.ttype {
+                        TokenType::BraceLeft => depth + 1,
+                        TokenType::BraceRight => depth - 1,
+                        _ => depth,
+                    });
+                depth > 0
+            }
+            Err(errors) => errors
+                .iter()
+                .any(|e| matches!(e, LexError::UnterminatedString(_))),
+        }
+    }
+
+    // При ошибке состояние разбора и компиляции возвращается к тому,
+    // что было до ввода строки
+    fn eval(&mut self, input: String) -> Result<(), HammerError> {
+        let mut lexer = Lexer::new(String::from(REPL_FILE), input);
+        lexer
+            .lex()
+            .map_err(|errors| ErrorKind::Lex(errors, vec![]))?;
+        let tokens = Self::terminate(lexer.tokens().to_vec());
+
+        let ast_builder = self.ast_builder.clone();
+        let compiler = self.compiler.clone();
+        let result = self.exec(tokens);
+        if result.is_err() {
+            self.ast_builder = ast_builder;
+            self.compiler = compiler;
+        }
+        result
+    }
+
+    fn exec(&mut self, tokens: Vec<Token>) -> Result<(), HammerError> {
+        let tree = self
+            .ast_builder
+            .parse_more(tokens)
+            .map_err(ErrorKind::Parse)?;
+        let start = self
+            .compiler
+            .compile_repl(tree)
+            .map_err(ErrorKind::Compile)?;
+        let bytecode = self.compiler.bytecode().map_err(ErrorKind::Compile)?;
+        self.vm.load(bytecode);
+        self.vm.run_from(start).map_err(ErrorKind::Interp)?;
+        Ok(())
+    }
+
+    // Точку с запятой в конце строки можно не писать
+    fn terminate(mut tokens: Vec<Token>) -> Vec<Token> {
+        let eof = tokens.len() - 1;
+        let terminated = eof == 0
+            || matches!(
+                tokens[eof - 1].ttype,
+                TokenType::Semicolon | TokenType::BraceRight
+            );
+        if !terminated {
+            let loc = tokens[eof].loc.clone();
+            tokens.insert(eof, Token::new(TokenType::Semicolon, loc));
+        }
+        tokens
+    }
+}
@@ -1,2 +1,6 @@
 pub mod compiler;
+pub mod encoder;
 pub mod errors;
+pub mod fold;
+pub mod ir;
+pub mod optimizer;
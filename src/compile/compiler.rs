@@ -1,193 +1,616 @@
+use crate::compile::encoder::Encoder;
 use crate::compile::errors::*;
-use crate::parser::ast::{Expr, Stmt, Variable};
-use crate::parser::tokens::{BIn, Token, TokenType};
-use crate::vm::vm::Value;
+use crate::compile::fold;
+use crate::compile::ir::{Inst, Ir, Label};
+use crate::parser::ast::{Expr, Function, Stmt, Variable};
+use crate::parser::tokens::{BIn, Loc, Token, TokenType};
+use crate::vm::host::HostFunctions;
+use crate::vm::vm::{Bytecode, Value, MAX_VARIABLES};
 use std::collections::HashMap;
-use std::fs;
-use std::io::{self, Write};
-use std::path;
 use std::rc::Rc;
 
+// Метки, на которые переходят break и continue внутри цикла
+#[derive(Clone)]
+struct LoopContext {
+    continue_label: Label,
+    break_label: Label,
+}
+
+// Ключ константы в таблице. Числа сравниваются побитово, чтобы 0 и -0
+// остались разными константами
+#[derive(Clone, PartialEq, Eq, Hash)]
+enum ConstKey {
+    Number(u64),
+    Bool(bool),
+    Nil,
+    Str(Rc<str>),
+}
+
+impl From<&Value> for ConstKey {
+    fn from(value: &Value) -> Self {
+        match value {
+            Value::Number(n) => Self::Number(n.to_bits()),
+            Value::Bool(b) => Self::Bool(*b),
+            Value::Nil => Self::Nil,
+            Value::Str(s) => Self::Str(s.clone()),
+        }
+    }
+}
+
+// Переводит AST в промежуточное представление (compile::ir),
+// байткод из него получает Encoder
+#[derive(Clone)]
 pub struct Compiler {
-    current_subtree: Option<Box<Expr>>,
-    file_name: String,
-    const_table: Vec<Value>,
+    ir: Ir,
+    // Индексы уже добавленных констант, чтобы не хранить одно значение дважды
+    const_indices: HashMap<ConstKey, u16>,
     variable_numbers: HashMap<Variable, u32>,
     last_variable_number: u32,
+    loops: Vec<LoopContext>,
+    // Метка начала и число параметров каждой объявленной функции
+    functions: HashMap<String, (Label, usize)>,
+    // Номер и число параметров каждой функции приложения
+    host_functions: HashMap<String, (u16, usize)>,
+    // Номера глобальных переменных, пока компилируется тело функции
+    globals: Option<HashMap<Variable, u32>>,
+    // Инициализированность глобальных переменных между строками REPL
+    initialized: HashMap<Variable, bool>,
+}
+
+impl Default for Compiler {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Compiler {
-    pub fn new(out_file_path: String) -> Result<Self, CompileError> {
-        let mut compiler = Self {
-            current_subtree: None,
-            file_name: out_file_path,
-            const_table: vec![],
+    pub fn new() -> Self {
+        Self {
+            ir: Ir::new(),
+            const_indices: HashMap::new(),
             variable_numbers: HashMap::new(),
             last_variable_number: 0,
-        };
-        Ok(compiler)
+            loops: vec![],
+            functions: HashMap::new(),
+            host_functions: HashMap::new(),
+            globals: None,
+            initialized: HashMap::new(),
+        }
+    }
+
+    pub fn with_host_functions(mut self, host_functions: &HostFunctions) -> Self {
+        for (index, function) in host_functions.iter().enumerate() {
+            let Ok(index) = u16::try_from(index) else {
+                panic!("Невозможная ситуация: HostFunctions::register не допускает больше 65536 функций");
+            };
+            self.host_functions
+                .insert(function.name.clone(), (index, function.arity));
+        }
+        self
     }
 
     pub fn compile(
         &mut self,
         tree: Vec<Stmt>,
         variables: Vec<Variable>,
-    ) -> Result<(), CompileError> {
-        let path = path::Path::new(&self.file_name);
-        let mut file = fs::OpenOptions::new()
-            .write(true)
-            .create(true)
-            .truncate(true)
-            .open(path)
-            .map_err(|e| CompileError::FileError(self.file_name.clone(), e))?;
+    ) -> Result<Bytecode, CompileError> {
+        let ir = self.lower(tree, variables)?;
+        Encoder::new(&ir).encode()
+    }
+
+    // Переводит программу в промежуточное представление
+    pub fn lower(&mut self, tree: Vec<Stmt>, variables: Vec<Variable>) -> Result<Ir, CompileError> {
         let mut initialized: HashMap<Variable, bool> = HashMap::new();
-        for stmt in tree {
-            match stmt {
-                Stmt::Expr(e) => {
-                    self.current_subtree = Some(e);
-                    self.compile_expr(&mut file, &mut initialized)?;
+        for stmt in fold::fold_tree(tree)? {
+            self.compile_stmt(stmt, &mut initialized)?;
+        }
+        self.ir.emit(Inst::Hlt);
+        self.const_indices.clear();
+        Ok(std::mem::take(&mut self.ir))
+    }
+
+    // Дописывает код очередной строки REPL после уже скомпилированного.
+    // Значения выражений верхнего уровня выводятся командой DBG.
+    // Возвращает адрес, с которого нужно продолжить выполнение
+    pub fn compile_repl(&mut self, tree: Vec<Stmt>) -> Result<usize, CompileError> {
+        let tree = fold::fold_tree(tree)?;
+        let start = self.ir.size();
+        let mut initialized = std::mem::take(&mut self.initialized);
+        let result = tree.into_iter().try_for_each(|stmt| match stmt {
+            Stmt::Expr(e) if Self::produces_value(&e) => {
+                self.compile_expr(&e, &mut initialized)?;
+                self.ir.emit(Inst::Dbg);
+                Ok(())
+            }
+            stmt => self.compile_stmt(stmt, &mut initialized),
+        });
+        self.initialized = initialized;
+        result?;
+        self.ir.emit(Inst::Hlt);
+        Ok(start)
+    }
+
+    // Номера глобальных переменных по именам. Если глобальная переменная
+    // объявлена несколько раз, берется последнее объявление
+    pub fn globals(&self) -> HashMap<String, u32> {
+        let mut vars: Vec<_> = self.variable_numbers.iter().collect();
+        vars.sort_by_key(|(var, _)| var.id);
+        vars.into_iter()
+            .map(|(var, number)| (var.name.clone(), *number))
+            .collect()
+    }
+
+    pub fn bytecode(&self) -> Result<Bytecode, CompileError> {
+        Encoder::new(&self.ir).encode()
+    }
+
+    fn compile_stmt(
+        &mut self,
+        stmt: Stmt,
+        initialized: &mut HashMap<Variable, bool>,
+    ) -> Result<(), CompileError> {
+        match stmt {
+            Stmt::Expr(e) => {
+                self.compile_expr(&e, initialized)?;
+                // Неиспользованное значение выражения убирается со стека
+                if Self::produces_value(&e) {
+                    self.ir.emit(Inst::Pop);
+                }
+                Ok(())
+            }
+            Stmt::Block(stmts) => {
+                let scope_start = self.last_variable_number;
+                for stmt in stmts {
+                    self.compile_stmt(stmt, initialized)?;
                 }
-                Stmt::Block(_) => todo!("Блоки выражений"),
-                Stmt::Decl(var, expr) => {
-                    self.compile_decl(&mut file, var, expr, &mut initialized)?
+                self.end_scope(scope_start);
+                Ok(())
+            }
+            Stmt::Decl(var, expr) => self.compile_decl(var, expr.as_deref(), initialized),
+            Stmt::Reassign(var, expr) => self.compile_reassign(var, &expr, initialized),
+            Stmt::If(cond, then_branch, else_branch) => {
+                self.compile_if(&cond, *then_branch, else_branch, initialized)
+            }
+            Stmt::While(cond, body) => self.compile_while(&cond, *body, initialized),
+            Stmt::For(var, start, end, body) => {
+                let scope_start = self.last_variable_number;
+                self.compile_for(var, &start, &end, *body, initialized)?;
+                self.end_scope(scope_start);
+                Ok(())
+            }
+            Stmt::Fn(function, body) => self.compile_fn(function, *body, initialized),
+            Stmt::Return(expr, loc) => {
+                if self.globals.is_none() {
+                    return Err(CompileError::ReturnOutsideFunction(loc));
                 }
-                Stmt::Reassign(var, expr) => {
-                    self.compile_reassign(&mut file, var, expr, &mut initialized)?
+                match expr {
+                    Some(expr) => self.compile_expr(&expr, initialized)?,
+                    None => self.emit_const(Value::Nil)?,
                 }
-            };
+                self.ir.emit(Inst::Ret);
+                Ok(())
+            }
+            Stmt::Break(loc) => {
+                let context = self
+                    .loops
+                    .last()
+                    .ok_or(CompileError::BreakOutsideLoop(loc))?;
+                self.ir.emit(Inst::Jmp(context.break_label));
+                Ok(())
+            }
+            Stmt::Continue(loc) => {
+                let context = self
+                    .loops
+                    .last()
+                    .ok_or(CompileError::ContinueOutsideLoop(loc))?;
+                self.ir.emit(Inst::Jmp(context.continue_label));
+                Ok(())
+            }
+        }
+    }
+
+    // Тело функции располагается прямо в коде и обходится переходом.
+    // При вызове аргументы лежат на стеке, и функция сама забирает
+    // их в свои переменные, начиная с последнего
+    fn compile_fn(
+        &mut self,
+        function: Function,
+        body: Stmt,
+        initialized: &HashMap<Variable, bool>,
+    ) -> Result<(), CompileError> {
+        let skip = self.ir.label();
+        self.ir.emit(Inst::Jmp(skip));
+        let entry = self.ir.label();
+        self.ir.place(entry);
+        self.ir.function(&function.name);
+        self.functions
+            .insert(function.name.clone(), (entry, function.params.len()));
+
+        let globals = std::mem::take(&mut self.variable_numbers);
+        let last_global_number = std::mem::replace(&mut self.last_variable_number, 0);
+        // break и continue в теле функции не относятся к внешнему циклу
+        let outer_loops = std::mem::take(&mut self.loops);
+        self.globals = Some(globals);
+        // Внутри функции видны глобальные переменные, инициализированные до ее объявления
+        let mut fn_initialized = initialized.clone();
+        for param in &function.params {
+            fn_initialized.insert(param.clone(), true);
+            let number = self.new_variable_number()?;
+            self.variable_numbers.insert(param.clone(), number);
         }
-        self.write_out(&[0xff], &mut file)?;
-        for c in &self.const_table {
-            // TODO: Типы
-            self.write_out(&[0x00, 0x08], &mut file)?;
-            let bytes = f64::to_le_bytes(*c);
-            self.write_out(&bytes, &mut file)?;
+        for number in (0..self.last_variable_number).rev() {
+            self.ir.emit(Inst::Liv(number));
         }
+        let result = self.compile_stmt(body, &mut fn_initialized);
+        self.variable_numbers = self.globals.take().unwrap();
+        self.last_variable_number = last_global_number;
+        self.loops = outer_loops;
+        result?;
+
+        // Функция без return возвращает nil
+        self.emit_const(Value::Nil)?;
+        self.ir.emit(Inst::Ret);
+        self.ir.place(skip);
+        Ok(())
+    }
+
+    fn compile_while(
+        &mut self,
+        cond: &Expr,
+        body: Stmt,
+        initialized: &mut HashMap<Variable, bool>,
+    ) -> Result<(), CompileError> {
+        let start = self.ir.label();
+        let end = self.ir.label();
+        self.ir.place(start);
+        self.compile_expr(cond, initialized)?;
+        self.ir.emit(Inst::Jf(end));
+
+        let context = LoopContext {
+            continue_label: start,
+            break_label: end,
+        };
+        self.compile_loop_body(body, context, initialized)?;
+        self.ir.emit(Inst::Jmp(start));
+        self.ir.place(end);
         Ok(())
     }
 
+    fn compile_for(
+        &mut self,
+        var: Variable,
+        start: &Expr,
+        end: &Expr,
+        body: Stmt,
+        initialized: &mut HashMap<Variable, bool>,
+    ) -> Result<(), CompileError> {
+        self.compile_decl(var.clone(), Some(start), initialized)?;
+        let var_number = self.variable_numbers[&var];
+        // Конец диапазона вычисляется один раз и хранится в скрытой переменной
+        let end_number = self.new_variable_number()?;
+        self.compile_expr(end, initialized)?;
+        self.ir.emit(Inst::Liv(end_number));
+
+        let loop_start = self.ir.label();
+        let next = self.ir.label();
+        let exit = self.ir.label();
+        self.ir.place(loop_start);
+        self.ir.emit(Inst::Lfv(var_number));
+        self.ir.emit(Inst::Lfv(end_number));
+        self.ir.emit(Inst::Ls);
+        self.ir.emit(Inst::Jf(exit));
+
+        let context = LoopContext {
+            continue_label: next,
+            break_label: exit,
+        };
+        self.compile_loop_body(body, context, initialized)?;
+        self.ir.place(next);
+        self.ir.emit(Inst::Lfv(var_number));
+        self.emit_const(Value::Number(1f64))?;
+        self.ir.emit(Inst::Add);
+        self.ir.emit(Inst::Liv(var_number));
+        self.ir.emit(Inst::Jmp(loop_start));
+        self.ir.place(exit);
+        Ok(())
+    }
+
+    fn compile_loop_body(
+        &mut self,
+        body: Stmt,
+        context: LoopContext,
+        initialized: &mut HashMap<Variable, bool>,
+    ) -> Result<(), CompileError> {
+        self.loops.push(context);
+        // Тело цикла может не выполниться ни разу
+        let mut body_initialized = initialized.clone();
+        let result = self.compile_stmt(body, &mut body_initialized);
+        self.loops.pop();
+        result?;
+        let before = initialized.clone();
+        Self::merge_initialized(initialized, &body_initialized, &before);
+        Ok(())
+    }
+
+    fn compile_if(
+        &mut self,
+        cond: &Expr,
+        then_branch: Stmt,
+        else_branch: Option<Box<Stmt>>,
+        initialized: &mut HashMap<Variable, bool>,
+    ) -> Result<(), CompileError> {
+        self.compile_expr(cond, initialized)?;
+        let else_label = self.ir.label();
+        self.ir.emit(Inst::Jf(else_label));
+
+        let mut then_initialized = initialized.clone();
+        self.compile_stmt(then_branch, &mut then_initialized)?;
+
+        // Без ветки else переменная после if инициализирована только если
+        // она была инициализирована до него
+        let mut else_initialized = initialized.clone();
+        match else_branch {
+            Some(else_branch) => {
+                let end = self.ir.label();
+                self.ir.emit(Inst::Jmp(end));
+                self.ir.place(else_label);
+                self.compile_stmt(*else_branch, &mut else_initialized)?;
+                self.ir.place(end);
+            }
+            None => self.ir.place(else_label),
+        }
+        Self::merge_initialized(initialized, &then_initialized, &else_initialized);
+        Ok(())
+    }
+
+    // Переменная инициализирована после ветвления, только если она
+    // инициализирована во всех ветках
+    fn merge_initialized(
+        initialized: &mut HashMap<Variable, bool>,
+        left: &HashMap<Variable, bool>,
+        right: &HashMap<Variable, bool>,
+    ) {
+        for var in left.keys().chain(right.keys()) {
+            let value = *left.get(var).unwrap_or(&false) && *right.get(var).unwrap_or(&false);
+            initialized.insert(var.clone(), value);
+        }
+    }
+
     fn compile_decl(
         &mut self,
-        file: &mut fs::File,
         var: Variable,
-        expr: Option<Box<Expr>>,
+        expr: Option<&Expr>,
         initialized: &mut HashMap<Variable, bool>,
     ) -> Result<(), CompileError> {
-        if expr.is_some() {
+        let number = self.new_variable_number()?;
+        if let Some(expr) = expr {
             initialized.insert(var.clone(), true);
-            self.current_subtree = expr;
-            self.compile_expr(file, initialized)?;
-            self.write_out(&[0x12], file)?;
-            let idx: [u8; 4] = u32::to_le_bytes(self.last_variable_number);
-            self.write_out(&idx, file)?;
+            self.compile_expr(expr, initialized)?;
+            self.ir.emit(Inst::Liv(number));
         } else {
             initialized.insert(var.clone(), false);
         }
-        self.variable_numbers.insert(var, self.last_variable_number);
-        self.last_variable_number += 1;
+        self.variable_numbers.insert(var, number);
         Ok(())
     }
 
     fn compile_reassign(
         &mut self,
-        file: &mut fs::File,
         var: Variable,
-        expr: Box<Expr>,
+        expr: &Expr,
         initialized: &mut HashMap<Variable, bool>,
     ) -> Result<(), CompileError> {
         initialized.insert(var.clone(), true);
-        self.current_subtree = Some(expr);
-        self.compile_expr(file, initialized)?;
-        let var_number = self.variable_numbers[&var];
-        self.write_out(&[0x12], file)?;
-        let idx: [u8; 4] = u32::to_le_bytes(var_number);
-        self.write_out(&idx, file)?;
+        self.compile_expr(expr, initialized)?;
+        self.emit_store(&var);
         Ok(())
     }
 
     fn compile_expr(
         &mut self,
-        file: &mut fs::File,
+        expr: &Expr,
         initialized: &mut HashMap<Variable, bool>,
     ) -> Result<(), CompileError> {
-        // В идеале здесь не должно быть клонирования, однако я просто
-        // уже не знаю как по другому сделать((
-        match *self.current_subtree.clone().unwrap() {
+        let span = expr.span();
+        match expr {
+            Expr::Binary(left, op, right)
+                if matches!(op.ttype, TokenType::OpAnd | TokenType::OpOr) =>
+            {
+                self.compile_logical(left, op, right, initialized)
+            }
             Expr::Binary(left, op, right) => {
-                self.current_subtree = Some(left);
-                self.compile_expr(file, initialized);
-                self.current_subtree = Some(right);
-                self.compile_expr(file, initialized);
-                match op.ttype {
-                    TokenType::OpPlus => self.write_out(&[0x02], file),
-                    TokenType::OpMinus => self.write_out(&[0x03], file),
-                    TokenType::OpStar => self.write_out(&[0x04], file),
-                    TokenType::OpSlash => self.write_out(&[0x05], file),
-                    _ => Err(CompileError::ExpectedOp(op.loc.clone())),
-                }
+                self.compile_expr(left, initialized)?;
+                self.compile_expr(right, initialized)?;
+                let inst = match op.ttype {
+                    TokenType::OpPlus => Inst::Add,
+                    TokenType::OpMinus => Inst::Sub,
+                    TokenType::OpStar => Inst::Mul,
+                    TokenType::OpSlash => Inst::Div,
+                    TokenType::OpEq => Inst::Eq,
+                    TokenType::OpNeq => Inst::Neq,
+                    TokenType::OpLess => Inst::Ls,
+                    TokenType::OpGreater => Inst::Gr,
+                    TokenType::OpLessEq => Inst::Le,
+                    TokenType::OpGreaterEq => Inst::Ge,
+                    _ => return Err(CompileError::ExpectedOp(op.loc.clone())),
+                };
+                self.ir.mark(&span);
+                self.ir.emit(inst);
+                Ok(())
             }
             Expr::Unary(op, expr) => {
-                self.current_subtree = Some(expr);
-                self.compile_expr(file, initialized);
+                self.compile_expr(expr, initialized)?;
+                self.ir.mark(&span);
                 match op.ttype {
-                    TokenType::OpMinus => self.write_out(&[0x06], file),
-                    _ => Err(CompileError::ExpectedOp(op.loc.clone())),
+                    TokenType::OpMinus => self.ir.emit(Inst::Neg),
+                    TokenType::OpNot => self.ir.emit(Inst::Not),
+                    _ => return Err(CompileError::ExpectedOp(op.loc.clone())),
                 }
+                Ok(())
             }
-            Expr::Grouping(expr) => {
-                self.current_subtree = Some(expr);
-                self.compile_expr(file, initialized)
-            }
+            Expr::Grouping(expr) => self.compile_expr(expr, initialized),
             Expr::Literal(val) => {
-                let index = self.const_table.len();
-                if index > u16::MAX as usize {
-                    return Err(CompileError::ConstTableOverflow);
-                }
-                let Token {
-                    ttype: TokenType::NumLit(value),
-                    loc: _,
-                } = val
-                else {
-                    panic!("Невозможная ситуация: refutable pattern")
+                let value = match &val.ttype {
+                    TokenType::NumLit(n) => Value::Number(*n),
+                    TokenType::StrLit(s) => Value::Str(s.as_str().into()),
+                    TokenType::BoolLit(b) => Value::Bool(*b),
+                    TokenType::Nil => Value::Nil,
+                    _ => panic!("Невозможная ситуация: литерал {val:?}"),
                 };
-                self.const_table.push(value);
-                self.write_out(&[0x01], file)?;
-                self.write_out(&u16::to_le_bytes(index as u16), file)
+                self.ir.mark(&val.loc);
+                self.emit_const(value)
             }
-            Expr::Func(func, expr) => {
-                self.current_subtree = Some(expr);
-                self.compile_expr(file, initialized);
-                match func.ttype {
-                    TokenType::Builtin(bin) => {
-                        self.write_out(&[0x11], file)?;
-                        match bin {
-                            BIn::Println => self.write_out(&[0x00, 0x00], file),
-                            BIn::Abs => self.write_out(&[0x01, 0x00], file),
-                        }
-                    }
-                    _ => todo!("Неопределенная функция {func:?}"),
+            Expr::Func(func, args) => {
+                let expected = match &func.ttype {
+                    TokenType::Builtin(bin) => bin.arity(),
+                    TokenType::Ident(name) => match self.functions.get(name) {
+                        Some((_, arity)) => *arity,
+                        None => self.host_functions[name].1,
+                    },
+                    _ => panic!("Невозможная ситуация: вызов не функции {func:?}"),
+                };
+                if args.len() != expected {
+                    return Err(CompileError::ArityMismatch(
+                        func.loc.clone(),
+                        expected,
+                        args.len(),
+                    ));
+                }
+                for arg in args {
+                    self.compile_expr(arg, initialized)?;
                 }
+                self.ir.mark(&func.loc);
+                let inst = match &func.ttype {
+                    TokenType::Builtin(bin) => Inst::Bin(bin.clone()),
+                    TokenType::Ident(name) => match self.functions.get(name) {
+                        Some((entry, _)) => Inst::Call(*entry),
+                        None => Inst::Hcall(self.host_functions[name].0),
+                    },
+                    _ => unreachable!(),
+                };
+                self.ir.emit(inst);
+                Ok(())
             }
             Expr::Variable(var, loc) => {
-                if !initialized.get(&var).unwrap() {
-                    return Err(CompileError::UninitializedVar(loc));
+                if !initialized.get(var).unwrap() {
+                    return Err(CompileError::UninitializedVar(loc.clone()));
                 }
-                let idx = *self.variable_numbers.get(&var).expect("На этапе построения дерева должно было быть определено, что эта переменная не объявлена");
-                self.write_out(&[0x13], file)?;
-                let idx_bytes = u32::to_le_bytes(idx);
-                self.write_out(&idx_bytes, file)
+                self.ir.mark(loc);
+                self.emit_load(var);
+                Ok(())
             }
             Expr::None => panic!("неожиданное появление AstNode::None"),
         }
     }
 
-    fn write_out(&self, bytes: &[u8], file: &mut fs::File) -> Result<(), CompileError> {
-        file.write(bytes)
-            .map(|_| ())
-            .map_err(|e| CompileError::FileError(self.file_name.clone(), e))
+    // && и || вычисляются по короткой схеме, результат всегда true или false:
+    //   a && b:  a; JF ложь; b; JF ложь; PUSH true; JMP конец; ложь: PUSH false; конец:
+    //   a || b:  a; JF b; JMP истина; b: b; JF ложь; истина: PUSH true; JMP конец; ложь: PUSH false; конец:
+    fn compile_logical(
+        &mut self,
+        left: &Expr,
+        op: &Token,
+        right: &Expr,
+        initialized: &mut HashMap<Variable, bool>,
+    ) -> Result<(), CompileError> {
+        let false_label = self.ir.label();
+        let true_label = self.ir.label();
+        let end = self.ir.label();
+        self.compile_expr(left, initialized)?;
+        if op.ttype == TokenType::OpAnd {
+            self.ir.emit(Inst::Jf(false_label));
+        } else {
+            let right_label = self.ir.label();
+            self.ir.emit(Inst::Jf(right_label));
+            self.ir.emit(Inst::Jmp(true_label));
+            self.ir.place(right_label);
+        }
+        self.compile_expr(right, initialized)?;
+        self.ir.emit(Inst::Jf(false_label));
+        self.ir.place(true_label);
+        self.emit_const(Value::Bool(true))?;
+        self.ir.emit(Inst::Jmp(end));
+        self.ir.place(false_label);
+        self.emit_const(Value::Bool(false))?;
+        self.ir.place(end);
+        Ok(())
+    }
+
+    // Номера переменных, объявленных в закончившейся области видимости,
+    // освобождаются и используются заново
+    fn end_scope(&mut self, scope_start: u32) {
+        self.variable_numbers
+            .retain(|_, number| *number < scope_start);
+        self.last_variable_number = scope_start;
+    }
+
+    // Одновременно видимых переменных не может быть больше MAX_VARIABLES
+    fn new_variable_number(&mut self) -> Result<u32, CompileError> {
+        let number = self.last_variable_number;
+        if number as usize >= MAX_VARIABLES {
+            return Err(CompileError::TooManyVariables);
+        }
+        self.last_variable_number += 1;
+        Ok(number)
     }
 
-    pub fn consts(&self) -> &Vec<f64> {
-        &self.const_table
+    // Переменные текущей функции перекрывают глобальные
+    fn variable_slot(&self, var: &Variable) -> (u32, bool) {
+        if let Some(number) = self.variable_numbers.get(var) {
+            return (*number, false);
+        }
+        let number = self
+            .globals
+            .as_ref()
+            .and_then(|globals| globals.get(var))
+            .expect("На этапе построения дерева должно было быть определено, что эта переменная не объявлена");
+        (*number, true)
+    }
+
+    fn emit_load(&mut self, var: &Variable) {
+        match self.variable_slot(var) {
+            (number, false) => self.ir.emit(Inst::Lfv(number)),
+            (number, true) => self.ir.emit(Inst::Lfg(number)),
+        }
+    }
+
+    fn emit_store(&mut self, var: &Variable) {
+        match self.variable_slot(var) {
+            (number, false) => self.ir.emit(Inst::Liv(number)),
+            (number, true) => self.ir.emit(Inst::Lig(number)),
+        }
+    }
+
+    fn emit_const(&mut self, value: Value) -> Result<(), CompileError> {
+        let index = self.add_const(value)?;
+        self.ir.emit(Inst::Push(index));
+        Ok(())
+    }
+
+    fn produces_value(expr: &Expr) -> bool {
+        !matches!(
+            expr,
+            Expr::Func(
+                Token {
+                    ttype: TokenType::Builtin(BIn::Println | BIn::Exit),
+                    ..
+                },
+                _
+            )
+        )
+    }
+
+    fn add_const(&mut self, value: Value) -> Result<u16, CompileError> {
+        let key = ConstKey::from(&value);
+        if let Some(index) = self.const_indices.get(&key) {
+            return Ok(*index);
+        }
+        let index = self.ir.consts.len();
+        if index > u16::MAX as usize {
+            return Err(CompileError::ConstTableOverflow);
+        }
+        self.ir.consts.push(value);
+        self.const_indices.insert(key, index as u16);
+        Ok(index as u16)
     }
 }
@@ -0,0 +1,90 @@
+use crate::compile::errors::CompileError;
+use crate::compile::ir::{Inst, Ir, Item, Label};
+use crate::vm::debug::DebugInfo;
+use crate::vm::instruction::Instruction;
+use crate::vm::vm::Bytecode;
+use std::collections::HashMap;
+
+// Переводит промежуточное представление в байткод: назначает командам
+// смещения, подставляет их вместо меток и собирает отладочную информацию
+pub struct Encoder<'a> {
+    ir: &'a Ir,
+    offsets: HashMap<Label, usize>,
+}
+
+impl<'a> Encoder<'a> {
+    pub fn new(ir: &'a Ir) -> Self {
+        Self {
+            ir,
+            offsets: HashMap::new(),
+        }
+    }
+
+    pub fn encode(mut self) -> Result<Bytecode, CompileError> {
+        let mut size = 0;
+        for item in &self.ir.items {
+            match item {
+                Item::Label(label) => {
+                    self.offsets.insert(*label, size);
+                }
+                Item::Inst(inst) => size += inst.size(),
+                _ => {}
+            }
+        }
+        u32::try_from(size).map_err(|_| CompileError::CodeTooLong)?;
+
+        let mut code = Vec::with_capacity(size);
+        let mut debug = DebugInfo::new();
+        for item in &self.ir.items {
+            match item {
+                Item::Inst(inst) => self.encode_inst(inst, &mut code)?,
+                Item::Loc(loc) => debug.add_line(code.len(), loc),
+                Item::Function(name) => debug.add_function(code.len(), name),
+                Item::Label(_) => {}
+            }
+        }
+        Ok(Bytecode {
+            code,
+            consts: self.ir.consts.clone(),
+            debug: Some(debug),
+        })
+    }
+
+    fn encode_inst(&self, inst: &Inst, code: &mut Vec<u8>) -> Result<(), CompileError> {
+        let offset = code.len();
+        let opcode = match inst {
+            Inst::Jmp(label) if self.offset(label) < offset => Instruction::Jback,
+            inst => inst.opcode(),
+        };
+        code.push(opcode as u8);
+        match inst {
+            Inst::Push(index) | Inst::Hcall(index) => code.extend_from_slice(&index.to_le_bytes()),
+            Inst::Bin(bin) => code.extend_from_slice(&bin.number().to_le_bytes()),
+            Inst::Liv(n) | Inst::Lfv(n) | Inst::Lig(n) | Inst::Lfg(n) => {
+                code.extend_from_slice(&n.to_le_bytes())
+            }
+            Inst::Jmp(label) | Inst::Jf(label) => {
+                let target = self.offset(label);
+                if matches!(inst, Inst::Jf(_)) && target < offset {
+                    panic!("Невозможная ситуация: JF назад на {label}");
+                }
+                let jump: u16 = target
+                    .abs_diff(offset)
+                    .try_into()
+                    .map_err(|_| CompileError::JumpTooLong)?;
+                code.extend_from_slice(&jump.to_le_bytes());
+            }
+            // Размер кода уже проверен, адрес помещается в u32
+            Inst::Call(label) => code.extend_from_slice(&(self.offset(label) as u32).to_le_bytes()),
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn offset(&self, label: &Label) -> usize {
+        *self
+            .offsets
+            .get(label)
+            .unwrap_or_else(|| panic!("Невозможная ситуация: метка {label} не поставлена"))
+    }
+}
@@ -1,21 +1,101 @@
+use crate::diagnostics::{Diagnostic, ToDiagnostic};
+use crate::locale::tr;
 use crate::parser::tokens::*;
+use crate::vm::vm::MAX_VARIABLES;
 use std::fmt;
 use std::io;
 
+#[derive(Debug)]
 pub enum CompileError {
     FileError(String, io::Error),
     ExpectedOp(Loc),
     ConstTableOverflow,
     UninitializedVar(Loc),
+    JumpTooLong,
+    BreakOutsideLoop(Loc),
+    ContinueOutsideLoop(Loc),
+    ReturnOutsideFunction(Loc),
+    ArityMismatch(Loc, usize, usize),
+    CodeTooLong,
+    DivisionByZero(Loc),
+    TooManyVariables,
 }
 
-impl fmt::Display for CompileError {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+impl CompileError {
+    pub fn code(&self) -> &'static str {
         match self {
-            Self::FileError(filename, error) => write!(f, "[{filename}] {error}"),
-            Self::ExpectedOp(loc) => write!(f, "[{loc}] ожидался знак операции"),
-            Self::ConstTableOverflow => write!(f, "переполнение таблицы констант"),
-            Self::UninitializedVar(loc) => write!(f, "[{loc}] переменная не инициализирована"),
+            Self::FileError(..) => "H0301",
+            Self::ExpectedOp(_) => "H0302",
+            Self::ConstTableOverflow => "H0303",
+            Self::UninitializedVar(_) => "H0304",
+            Self::JumpTooLong => "H0305",
+            Self::BreakOutsideLoop(_) => "H0306",
+            Self::ContinueOutsideLoop(_) => "H0307",
+            Self::ReturnOutsideFunction(_) => "H0308",
+            Self::ArityMismatch(..) => "H0309",
+            Self::CodeTooLong => "H0310",
+            Self::DivisionByZero(_) => "H0311",
+            Self::TooManyVariables => "H0312",
         }
     }
 }
+
+impl ToDiagnostic for CompileError {
+    fn diagnostic(&self) -> Diagnostic {
+        let diagnostic = match self {
+            Self::FileError(filename, error) => Diagnostic::new(format!("[{filename}] {error}")),
+            Self::ExpectedOp(loc) => {
+                Diagnostic::new(tr!("ожидался знак операции", "expected operator")).at(loc)
+            }
+            Self::ConstTableOverflow => Diagnostic::new(tr!(
+                "переполнение таблицы констант",
+                "constant table overflow"
+            )),
+            Self::UninitializedVar(loc) => Diagnostic::new(tr!(
+                "переменная не инициализирована",
+                "variable is not initialized"
+            ))
+            .at(loc)
+            .with_note(tr!(
+                "переменная должна получить значение до этого места при любом ходе выполнения",
+                "the variable must be assigned before this point on every execution path"
+            )),
+            Self::JumpTooLong => Diagnostic::new(tr!(
+                "слишком длинный переход: тело ветвления или цикла превышает 65535 байт",
+                "jump is too long: the body of a branch or loop exceeds 65535 bytes"
+            )),
+            Self::BreakOutsideLoop(loc) => {
+                Diagnostic::new(tr!("break вне цикла", "break outside of a loop")).at(loc)
+            }
+            Self::ContinueOutsideLoop(loc) => {
+                Diagnostic::new(tr!("continue вне цикла", "continue outside of a loop")).at(loc)
+            }
+            Self::ReturnOutsideFunction(loc) => {
+                Diagnostic::new(tr!("return вне функции", "return outside of a function")).at(loc)
+            }
+            Self::ArityMismatch(loc, expected, got) => Diagnostic::new(tr!(
+                "неверное число аргументов: ожидалось {expected}, передано {got}",
+                "wrong number of arguments: expected {expected}, got {got}"
+            ))
+            .at(loc),
+            Self::CodeTooLong => Diagnostic::new(tr!(
+                "размер кода превышает 4294967295 байт",
+                "code size exceeds 4294967295 bytes"
+            )),
+            Self::DivisionByZero(loc) => Diagnostic::new(tr!("деление на 0", "division by zero"))
+                .at(loc)
+                .with_label(tr!("делитель равен нулю", "the divisor is zero")),
+            Self::TooManyVariables => Diagnostic::new(tr!(
+                "одновременно видно больше {MAX_VARIABLES} переменных",
+                "more than {MAX_VARIABLES} variables are visible at once"
+            )),
+        };
+        diagnostic.with_code(self.code())
+    }
+}
+
+impl fmt::Display for CompileError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.diagnostic())
+    }
+}
@@ -0,0 +1,165 @@
+use crate::compile::errors::CompileError;
+use crate::parser::ast::{Expr, Stmt};
+use crate::parser::tokens::{BIn, Loc, Token, TokenType};
+use crate::vm::vm::Value;
+use std::cmp::Ordering;
+
+// Свертка констант: поддеревья выражений, в которых есть только литералы,
+// вычисляются при компиляции и заменяются одним литералом. Вычисление
+// повторяет поведение VM; если при выполнении операция закончилась бы
+// ошибкой типа, поддерево остается как есть и ошибка возникнет в VM
+pub fn fold_tree(tree: Vec<Stmt>) -> Result<Vec<Stmt>, CompileError> {
+    tree.into_iter().map(fold_stmt).collect()
+}
+
+fn fold_stmt(stmt: Stmt) -> Result<Stmt, CompileError> {
+    let stmt = match stmt {
+        Stmt::Block(stmts) => Stmt::Block(fold_tree(stmts)?),
+        Stmt::Expr(expr) => Stmt::Expr(fold_boxed(expr)?),
+        Stmt::Decl(var, expr) => Stmt::Decl(var, expr.map(fold_boxed).transpose()?),
+        Stmt::Reassign(var, expr) => Stmt::Reassign(var, fold_boxed(expr)?),
+        Stmt::If(cond, then, otherwise) => Stmt::If(
+            fold_boxed(cond)?,
+            fold_boxed_stmt(then)?,
+            otherwise.map(fold_boxed_stmt).transpose()?,
+        ),
+        Stmt::While(cond, body) => Stmt::While(fold_boxed(cond)?, fold_boxed_stmt(body)?),
+        Stmt::For(var, start, end, body) => Stmt::For(
+            var,
+            fold_boxed(start)?,
+            fold_boxed(end)?,
+            fold_boxed_stmt(body)?,
+        ),
+        Stmt::Fn(func, body) => Stmt::Fn(func, fold_boxed_stmt(body)?),
+        Stmt::Return(expr, loc) => Stmt::Return(expr.map(fold_boxed).transpose()?, loc),
+        stmt @ (Stmt::Break(_) | Stmt::Continue(_)) => stmt,
+    };
+    Ok(stmt)
+}
+
+fn fold_boxed_stmt(mut stmt: Box<Stmt>) -> Result<Box<Stmt>, CompileError> {
+    *stmt = fold_stmt(*stmt)?;
+    Ok(stmt)
+}
+
+fn fold_boxed(mut expr: Box<Expr>) -> Result<Box<Expr>, CompileError> {
+    *expr = fold_expr(*expr)?;
+    Ok(expr)
+}
+
+fn fold_expr(expr: Expr) -> Result<Expr, CompileError> {
+    let span = expr.span();
+    let folded = match expr {
+        Expr::Grouping(inner) => match fold_expr(*inner)? {
+            literal @ Expr::Literal(_) => literal,
+            inner => Expr::Grouping(Box::new(inner)),
+        },
+        Expr::Unary(op, operand) => {
+            let operand = fold_expr(*operand)?;
+            match literal(&operand).and_then(|value| unary(&op.ttype, value)) {
+                Some(value) => to_literal(value, span),
+                None => Expr::Unary(op, Box::new(operand)),
+            }
+        }
+        Expr::Binary(left, op, right) => {
+            let left = fold_expr(*left)?;
+            let right = fold_expr(*right)?;
+            if op.ttype == TokenType::OpSlash && literal(&right) == Some(Value::Number(0f64)) {
+                return Err(CompileError::DivisionByZero(right.span()));
+            }
+            let value = match (literal(&left), literal(&right)) {
+                (Some(a), Some(b)) => binary(&op.ttype, a, b),
+                _ => None,
+            };
+            match value {
+                Some(value) => to_literal(value, span),
+                None => Expr::Binary(Box::new(left), op, Box::new(right)),
+            }
+        }
+        Expr::Func(func, args) => {
+            let args = args
+                .into_iter()
+                .map(fold_expr)
+                .collect::<Result<Vec<_>, _>>()?;
+            match (&func.ttype, args.as_slice()) {
+                (TokenType::Builtin(BIn::Abs), [arg]) => match literal(arg) {
+                    Some(Value::Number(n)) => to_literal(Value::Number(n.abs()), span),
+                    _ => Expr::Func(func, args),
+                },
+                _ => Expr::Func(func, args),
+            }
+        }
+        expr => expr,
+    };
+    Ok(folded)
+}
+
+// Значение выражения, если это литерал
+fn literal(expr: &Expr) -> Option<Value> {
+    let Expr::Literal(token) = expr else {
+        return None;
+    };
+    match &token.ttype {
+        TokenType::NumLit(n) => Some(Value::Number(*n)),
+        TokenType::StrLit(s) => Some(Value::Str(s.as_str().into())),
+        TokenType::BoolLit(b) => Some(Value::Bool(*b)),
+        TokenType::Nil => Some(Value::Nil),
+        _ => None,
+    }
+}
+
+fn to_literal(value: Value, loc: Loc) -> Expr {
+    let ttype = match value {
+        Value::Number(n) => TokenType::NumLit(n),
+        Value::Str(s) => TokenType::StrLit(s.to_string()),
+        Value::Bool(b) => TokenType::BoolLit(b),
+        Value::Nil => TokenType::Nil,
+    };
+    Expr::Literal(Token::new(ttype, loc))
+}
+
+fn unary(op: &TokenType, value: Value) -> Option<Value> {
+    match (op, value) {
+        (TokenType::OpMinus, Value::Number(n)) => Some(Value::Number(-n)),
+        (TokenType::OpNot, value) => Some(Value::Bool(!value.is_truthy())),
+        _ => None,
+    }
+}
+
+fn binary(op: &TokenType, a: Value, b: Value) -> Option<Value> {
+    let value = match (op, a, b) {
+        (TokenType::OpPlus, Value::Str(a), Value::Str(b)) => Value::Str(format!("{a}{b}").into()),
+        (TokenType::OpPlus, Value::Number(a), Value::Number(b)) => Value::Number(a + b),
+        (TokenType::OpMinus, Value::Number(a), Value::Number(b)) => Value::Number(a - b),
+        (TokenType::OpStar, Value::Number(a), Value::Number(b)) => Value::Number(a * b),
+        (TokenType::OpSlash, Value::Number(a), Value::Number(b)) => Value::Number(a / b),
+        (TokenType::OpEq, a, b) => Value::Bool(a == b),
+        (TokenType::OpNeq, a, b) => Value::Bool(a != b),
+        (TokenType::OpAnd, a, b) => Value::Bool(a.is_truthy() && b.is_truthy()),
+        (TokenType::OpOr, a, b) => Value::Bool(a.is_truthy() || b.is_truthy()),
+        (
+            op @ (TokenType::OpLess
+            | TokenType::OpGreater
+            | TokenType::OpLessEq
+            | TokenType::OpGreaterEq),
+            a,
+            b,
+        ) => {
+            // Для NaN partial_cmp дает None, и любое сравнение ложно, как в VM
+            let ordering = match (a, b) {
+                (Value::Number(a), Value::Number(b)) => a.partial_cmp(&b),
+                (Value::Str(a), Value::Str(b)) => Some(a.cmp(&b)),
+                _ => return None,
+            };
+            let result = match op {
+                TokenType::OpLess => ordering == Some(Ordering::Less),
+                TokenType::OpGreater => ordering == Some(Ordering::Greater),
+                TokenType::OpLessEq => matches!(ordering, Some(Ordering::Less | Ordering::Equal)),
+                _ => matches!(ordering, Some(Ordering::Greater | Ordering::Equal)),
+            };
+            Value::Bool(result)
+        }
+        _ => return None,
+    };
+    Some(value)
+}
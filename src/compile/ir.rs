@@ -0,0 +1,259 @@
+use crate::parser::tokens::{BIn, Loc};
+use crate::vm::instruction::Instruction;
+use crate::vm::vm::Value;
+use std::fmt;
+
+// Метка в коде. Номера меток уникальны в пределах программы
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Label(u32);
+
+impl fmt::Display for Label {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "L{}", self.0)
+    }
+}
+
+// Команда VM с типизированным операндом. Переходы и вызовы ссылаются на метки,
+// смещения вычисляет Encoder
+#[derive(Clone, Debug, PartialEq)]
+pub enum Inst {
+    Nop,
+    // Индекс константы в Ir::consts
+    Push(u16),
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Neg,
+    Not,
+    Eq,
+    Neq,
+    Gr,
+    Ls,
+    Ge,
+    Le,
+    // Безусловный переход вперед или назад (в байткоде JMP или JBACK)
+    Jmp(Label),
+    // Переход, если на стеке ложное значение. Возможен только вперед
+    Jf(Label),
+    Bin(BIn),
+    Call(Label),
+    Ret,
+    Hcall(u16),
+    Liv(u32),
+    Lfv(u32),
+    Lig(u32),
+    Lfg(u32),
+    Pop,
+    Dbg,
+    Hlt,
+}
+
+impl Inst {
+    pub fn opcode(&self) -> Instruction {
+        match self {
+            Self::Nop => Instruction::Nop,
+            Self::Push(_) => Instruction::Push,
+            Self::Add => Instruction::Add,
+            Self::Sub => Instruction::Sub,
+            Self::Mul => Instruction::Mul,
+            Self::Div => Instruction::Div,
+            Self::Neg => Instruction::Neg,
+            Self::Not => Instruction::Not,
+            Self::Eq => Instruction::Eq,
+            Self::Neq => Instruction::Neq,
+            Self::Gr => Instruction::Gr,
+            Self::Ls => Instruction::Ls,
+            Self::Ge => Instruction::Ge,
+            Self::Le => Instruction::Le,
+            Self::Jmp(_) => Instruction::Jmp,
+            Self::Jf(_) => Instruction::Jf,
+            Self::Bin(_) => Instruction::Bin,
+            Self::Call(_) => Instruction::Call,
+            Self::Ret => Instruction::Ret,
+            Self::Hcall(_) => Instruction::Hcall,
+            Self::Liv(_) => Instruction::Liv,
+            Self::Lfv(_) => Instruction::Lfv,
+            Self::Lig(_) => Instruction::Lig,
+            Self::Lfg(_) => Instruction::Lfg,
+            Self::Pop => Instruction::Pop,
+            Self::Dbg => Instruction::Dbg,
+            Self::Hlt => Instruction::Hlt,
+        }
+    }
+
+    // Размер команды в байткоде вместе с операндами
+    pub fn size(&self) -> usize {
+        1 + self.opcode().operands_size()
+    }
+
+    pub fn target(&self) -> Option<Label> {
+        match self {
+            Self::Jmp(label) | Self::Jf(label) | Self::Call(label) => Some(*label),
+            _ => None,
+        }
+    }
+
+    // После команды выполнение не переходит к следующей
+    pub fn is_terminator(&self) -> bool {
+        matches!(self, Self::Jmp(_) | Self::Ret | Self::Hlt)
+    }
+}
+
+impl fmt::Display for Inst {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let opcode = self.opcode();
+        match self {
+            Self::Push(index) | Self::Hcall(index) => write!(f, "{opcode:<6} {index}"),
+            Self::Jmp(label) | Self::Jf(label) | Self::Call(label) => {
+                write!(f, "{opcode:<6} {label}")
+            }
+            Self::Bin(bin) => write!(f, "{opcode:<6} {}", bin.name()),
+            Self::Liv(n) | Self::Lfv(n) | Self::Lig(n) | Self::Lfg(n) => {
+                write!(f, "{opcode:<6} {n}")
+            }
+            _ => write!(f, "{opcode}"),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub enum Item {
+    Inst(Inst),
+    // Метка указывает на следующую команду
+    Label(Label),
+    // Следующие команды относятся к месту loc (для сообщений об ошибках выполнения)
+    Loc(Loc),
+    // Здесь начинается функция с таким именем
+    Function(String),
+}
+
+// Базовый блок: начинается с меток, на которые возможен переход,
+// и заканчивается переходом, RET или HLT. Внутрь блока переходов нет
+pub struct Block<'a> {
+    pub labels: Vec<Label>,
+    pub items: &'a [Item],
+}
+
+// Промежуточное представление программы между AST и байткодом:
+// команды с символическими метками и таблица констант
+#[derive(Clone, Default)]
+pub struct Ir {
+    pub items: Vec<Item>,
+    pub consts: Vec<Value>,
+    labels: u32,
+}
+
+impl Ir {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Новая, еще не поставленная метка
+    pub fn label(&mut self) -> Label {
+        self.labels += 1;
+        Label(self.labels - 1)
+    }
+
+    pub fn emit(&mut self, inst: Inst) {
+        self.items.push(Item::Inst(inst));
+    }
+
+    // Ставит метку перед следующей командой
+    pub fn place(&mut self, label: Label) {
+        self.items.push(Item::Label(label));
+    }
+
+    pub fn mark(&mut self, loc: &Loc) {
+        self.items.push(Item::Loc(loc.clone()));
+    }
+
+    pub fn function(&mut self, name: &str) {
+        self.items.push(Item::Function(name.to_string()));
+    }
+
+    // Размер кода в байтах
+    pub fn size(&self) -> usize {
+        self.insts().map(Inst::size).sum()
+    }
+
+    pub fn insts(&self) -> impl Iterator<Item = &Inst> {
+        self.items.iter().filter_map(|item| match item {
+            Item::Inst(inst) => Some(inst),
+            _ => None,
+        })
+    }
+
+    pub fn blocks(&self) -> Vec<Block<'_>> {
+        let mut blocks = vec![];
+        let mut start = 0;
+        let mut labels = vec![];
+        let mut has_insts = false;
+        for (i, item) in self.items.iter().enumerate() {
+            let ends_block = match item {
+                Item::Label(label) => {
+                    if has_insts {
+                        blocks.push(Block {
+                            labels: std::mem::take(&mut labels),
+                            items: &self.items[start..i],
+                        });
+                        has_insts = false;
+                    }
+                    labels.push(*label);
+                    start = i + 1;
+                    false
+                }
+                Item::Inst(inst) => {
+                    has_insts = true;
+                    inst.is_terminator() || matches!(inst, Inst::Jf(_))
+                }
+                _ => false,
+            };
+            if ends_block {
+                blocks.push(Block {
+                    labels: std::mem::take(&mut labels),
+                    items: &self.items[start..=i],
+                });
+                start = i + 1;
+                has_insts = false;
+            }
+        }
+        if start < self.items.len() || !labels.is_empty() {
+            blocks.push(Block {
+                labels,
+                items: &self.items[start..],
+            });
+        }
+        blocks
+    }
+}
+
+// Листинг для --emit=ir: блоки разделены пустой строкой
+impl fmt::Display for Ir {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (i, block) in self.blocks().iter().enumerate() {
+            if i > 0 {
+                writeln!(f)?;
+            }
+            for label in &block.labels {
+                writeln!(f, "{label}:")?;
+            }
+            for item in block.items {
+                match item {
+                    Item::Inst(inst @ Inst::Push(index)) => {
+                        let line = format!("    {inst}");
+                        match self.consts.get(*index as usize) {
+                            Some(c) => writeln!(f, "{line:<20} ; {c:#}")?,
+                            None => writeln!(f, "{line}")?,
+                        }
+                    }
+                    Item::Inst(inst) => writeln!(f, "    {inst}")?,
+                    Item::Loc(loc) => writeln!(f, "    ; {}:{}", loc.line(), loc.col())?,
+                    Item::Function(name) => writeln!(f, "fn {name}:")?,
+                    Item::Label(_) => {}
+                }
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,311 @@
+use crate::vm::instruction::Instruction;
+use crate::vm::vm::{Bytecode, Value};
+use std::collections::{HashMap, HashSet};
+
+// Команда с операндом. У переходов и CALL операнд - номер команды, на которую
+// выполняется переход (номер, равный числу команд, означает конец кода)
+#[derive(Clone, Copy)]
+struct Op {
+    inst: Instruction,
+    operand: u32,
+    // Смещение команды в исходном коде, нужно для переноса отладочной информации
+    offset: usize,
+}
+
+impl Op {
+    fn is_jump(&self) -> bool {
+        matches!(
+            self.inst,
+            Instruction::Jmp | Instruction::Jf | Instruction::Jback | Instruction::Call
+        )
+    }
+}
+
+// Оптимизатор "через глазок" для байткода, созданного компилятором.
+// Удаляет NOP, пары NEG NEG после числовой константы и ненужные пары загрузки
+// и сохранения переменной, сокращает цепочки переходов и удаляет недостижимый код
+pub struct Optimizer {
+    ops: Vec<Op>,
+    // Номер первой команды каждой вызываемой функции по ее исходному адресу
+    functions: HashMap<usize, usize>,
+    bytecode: Bytecode,
+}
+
+impl Optimizer {
+    pub fn new(bytecode: Bytecode) -> Self {
+        let mut ops = vec![];
+        let mut index_of = HashMap::new();
+        let mut offset = 0;
+        while offset < bytecode.code.len() {
+            let Ok(inst) = Instruction::try_from(bytecode.code[offset]) else {
+                panic!("Невозможная ситуация: компилятор создал неизвестную команду");
+            };
+            let operands = &bytecode.code[(offset + 1)..(offset + 1 + inst.operands_size())];
+            let operand = match *operands {
+                [a, b] => u16::from_le_bytes([a, b]) as u32,
+                [a, b, c, d] => u32::from_le_bytes([a, b, c, d]),
+                _ => 0,
+            };
+            index_of.insert(offset, ops.len());
+            ops.push(Op {
+                inst,
+                operand,
+                offset,
+            });
+            offset += 1 + inst.operands_size();
+        }
+        index_of.insert(offset, ops.len());
+        let mut functions = HashMap::new();
+        for op in ops.iter_mut() {
+            let target = match op.inst {
+                Instruction::Jmp | Instruction::Jf => op.offset + op.operand as usize,
+                Instruction::Jback => op.offset - op.operand as usize,
+                Instruction::Call => op.operand as usize,
+                _ => continue,
+            };
+            op.operand = index_of[&target] as u32;
+            if op.inst == Instruction::Call {
+                functions.insert(target, index_of[&target]);
+            }
+        }
+        Self {
+            ops,
+            functions,
+            bytecode,
+        }
+    }
+
+    pub fn optimize(mut self) -> Bytecode {
+        loop {
+            let before = self.ops.len();
+            self.remove_nops();
+            self.remove_pairs();
+            let threaded = self.thread_jumps();
+            self.remove_unreachable();
+            if !threaded && self.ops.len() == before {
+                break;
+            }
+        }
+        self.encode()
+    }
+
+    fn remove_nops(&mut self) {
+        let keep: Vec<bool> = self
+            .ops
+            .iter()
+            .map(|op| op.inst != Instruction::Nop)
+            .collect();
+        self.retain(&keep);
+    }
+
+    // NEG NEG сразу после PUSH числа (для не-числа NEG дает ошибку типа),
+    // загрузка переменной и сохранение обратно (LFV x; LIV x),
+    // а также сохранение и сразу загрузка (LIV x; LFV x), если больше
+    // переменная нигде не читается. Пара не трогается, если на ее вторую
+    // команду есть переход
+    fn remove_pairs(&mut self) {
+        let targets = self.targets();
+        let mut reads: HashMap<u32, usize> = HashMap::new();
+        for op in &self.ops {
+            if matches!(op.inst, Instruction::Lfv | Instruction::Lfg) {
+                *reads.entry(op.operand).or_default() += 1;
+            }
+        }
+        let mut keep = vec![true; self.ops.len()];
+        let mut i = 0;
+        while i + 1 < self.ops.len() {
+            let (first, second) = (self.ops[i], self.ops[i + 1]);
+            let same_var = first.operand == second.operand;
+            let removable = match (first.inst, second.inst) {
+                (Instruction::Neg, Instruction::Neg) => {
+                    self.pushes_number(i.wrapping_sub(1)) && !targets.contains(&i)
+                }
+                (Instruction::Lfv, Instruction::Liv) | (Instruction::Lfg, Instruction::Lig) => {
+                    same_var
+                }
+                (Instruction::Liv, Instruction::Lfv) | (Instruction::Lig, Instruction::Lfg) => {
+                    same_var && reads[&second.operand] == 1
+                }
+                _ => false,
+            };
+            if removable && !targets.contains(&(i + 1)) {
+                keep[i] = false;
+                keep[i + 1] = false;
+                i += 2;
+            } else {
+                i += 1;
+            }
+        }
+        self.retain(&keep);
+    }
+
+    // Переход на JMP или JBACK заменяется переходом сразу в конец цепочки,
+    // JMP на следующую команду удаляется. JF умеет переходить только вперед,
+    // поэтому его цель может быть только дальше него
+    fn thread_jumps(&mut self) -> bool {
+        let mut changed = false;
+        for i in 0..self.ops.len() {
+            let op = self.ops[i];
+            if !matches!(
+                op.inst,
+                Instruction::Jmp | Instruction::Jf | Instruction::Jback
+            ) {
+                continue;
+            }
+            let mut target = op.operand as usize;
+            let mut seen = HashSet::from([i]);
+            while let Some(next) = self.ops.get(target) {
+                if !matches!(next.inst, Instruction::Jmp | Instruction::Jback)
+                    || !seen.insert(target)
+                {
+                    break;
+                }
+                target = next.operand as usize;
+            }
+            if target != op.operand as usize && self.encodable(i, target) {
+                let inst = match op.inst {
+                    Instruction::Jf => Instruction::Jf,
+                    _ if target > i => Instruction::Jmp,
+                    _ => Instruction::Jback,
+                };
+                self.ops[i].inst = inst;
+                self.ops[i].operand = target as u32;
+                changed = true;
+            }
+        }
+        let keep: Vec<bool> = self
+            .ops
+            .iter()
+            .enumerate()
+            .map(|(i, op)| op.inst != Instruction::Jmp || op.operand as usize != i + 1)
+            .collect();
+        self.retain(&keep);
+        changed
+    }
+
+    // Можно ли записать переход от команды from к команде to. Проверяются
+    // исходные смещения: удаление команд только сокращает расстояния
+    fn encodable(&self, from: usize, to: usize) -> bool {
+        if self.ops[from].inst == Instruction::Jf && to <= from {
+            return false;
+        }
+        let end = self
+            .ops
+            .last()
+            .map_or(0, |op| op.offset + 1 + op.inst.operands_size());
+        let to = self.ops.get(to).map_or(end, |op| op.offset);
+        to.abs_diff(self.ops[from].offset) <= u16::MAX as usize
+    }
+
+    // Удаляет команды, до которых не доходит выполнение ни по одному пути
+    fn remove_unreachable(&mut self) {
+        let mut keep = vec![false; self.ops.len()];
+        let mut queue = vec![0];
+        while let Some(i) = queue.pop() {
+            if i >= self.ops.len() || keep[i] {
+                continue;
+            }
+            keep[i] = true;
+            let op = self.ops[i];
+            match op.inst {
+                Instruction::Jmp | Instruction::Jback => queue.push(op.operand as usize),
+                Instruction::Jf | Instruction::Call => {
+                    queue.push(op.operand as usize);
+                    queue.push(i + 1);
+                }
+                Instruction::Hlt | Instruction::Ret => {}
+                _ => queue.push(i + 1),
+            }
+        }
+        self.functions
+            .retain(|_, entry| keep.get(*entry) == Some(&true));
+        self.retain(&keep);
+    }
+
+    fn pushes_number(&self, i: usize) -> bool {
+        match self.ops.get(i) {
+            Some(op) if op.inst == Instruction::Push => matches!(
+                self.bytecode.consts.get(op.operand as usize),
+                Some(Value::Number(_))
+            ),
+            _ => false,
+        }
+    }
+
+    fn targets(&self) -> HashSet<usize> {
+        self.ops
+            .iter()
+            .filter(|op| op.is_jump())
+            .map(|op| op.operand as usize)
+            .collect()
+    }
+
+    // Оставляет команды, отмеченные в keep. Переходы на удаленную команду
+    // направляются на следующую оставшуюся
+    fn retain(&mut self, keep: &[bool]) {
+        let mut new_index = Vec::with_capacity(self.ops.len() + 1);
+        let mut count = 0;
+        for &kept in keep {
+            new_index.push(count);
+            count += kept as u32;
+        }
+        new_index.push(count);
+        let mut ops = Vec::with_capacity(count as usize);
+        for (op, &kept) in self.ops.iter().zip(keep) {
+            if kept {
+                let mut op = *op;
+                if op.is_jump() {
+                    op.operand = new_index[op.operand as usize];
+                }
+                ops.push(op);
+            }
+        }
+        self.ops = ops;
+        for entry in self.functions.values_mut() {
+            *entry = new_index[*entry] as usize;
+        }
+    }
+
+    fn encode(self) -> Bytecode {
+        let mut offsets = Vec::with_capacity(self.ops.len() + 1);
+        let mut offset = 0;
+        for op in &self.ops {
+            offsets.push(offset);
+            offset += 1 + op.inst.operands_size();
+        }
+        offsets.push(offset);
+        let mut code = Vec::with_capacity(offset);
+        for (i, op) in self.ops.iter().enumerate() {
+            let operand = match op.inst {
+                Instruction::Jmp | Instruction::Jf => offsets[op.operand as usize] - offsets[i],
+                Instruction::Jback => offsets[i] - offsets[op.operand as usize],
+                Instruction::Call => offsets[op.operand as usize],
+                _ => op.operand as usize,
+            };
+            code.push(op.inst as u8);
+            match op.inst.operands_size() {
+                2 => code.extend_from_slice(&u16::to_le_bytes(
+                    operand
+                        .try_into()
+                        .expect("Оптимизация не удлиняет переходы"),
+                )),
+                4 => code.extend_from_slice(&u32::to_le_bytes(operand as u32)),
+                _ => {}
+            }
+        }
+        // Старое смещение переходит в смещение первой оставшейся команды
+        // не раньше него
+        let old_offsets: Vec<usize> = self.ops.iter().map(|op| op.offset).collect();
+        let relocate = |old: usize| offsets[old_offsets.partition_point(|&o| o < old)];
+        let function = |old: usize| self.functions.get(&old).map(|&entry| offsets[entry]);
+        let debug = self
+            .bytecode
+            .debug
+            .map(|debug| debug.relocate(relocate, function));
+        Bytecode {
+            code,
+            consts: self.bytecode.consts,
+            debug,
+        }
+    }
+}
@@ -0,0 +1,274 @@
+use crate::locale::tr;
+use crate::parser::tokens::Loc;
+use std::{
+    collections::HashMap,
+    env, fmt, fs,
+    io::{self, IsTerminal},
+};
+
+// Сообщение об ошибке вместе с местом в исходном коде и пояснениями
+pub struct Diagnostic {
+    // Код ошибки не зависит от языка сообщений. Список кодов - в docs/errors.txt
+    pub code: Option<&'static str>,
+    pub message: String,
+    pub loc: Option<Loc>,
+    // Подпись под подчеркнутым участком кода
+    pub label: Option<String>,
+    pub notes: Vec<String>,
+}
+
+impl Diagnostic {
+    pub fn new(message: impl Into<String>) -> Self {
+        Self {
+            code: None,
+            message: message.into(),
+            loc: None,
+            label: None,
+            notes: vec![],
+        }
+    }
+
+    pub fn with_code(mut self, code: &'static str) -> Self {
+        self.code = Some(code);
+        self
+    }
+
+    pub fn at(mut self, loc: &Loc) -> Self {
+        self.loc = Some(loc.clone());
+        self
+    }
+
+    pub fn with_label(mut self, label: impl Into<String>) -> Self {
+        self.label = Some(label.into());
+        self
+    }
+
+    pub fn with_note(mut self, note: impl Into<String>) -> Self {
+        self.notes.push(note.into());
+        self
+    }
+}
+
+// Краткая форма без исходного кода: код, [место] сообщение и пояснения
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let Some(code) = self.code {
+            write!(f, "{code}: ")?;
+        }
+        match &self.loc {
+            Some(loc) => write!(f, "[{loc}] {}", self.message)?,
+            None => write!(f, "{}", self.message)?,
+        }
+        for note in &self.notes {
+            write!(f, "\n{note}")?;
+        }
+        Ok(())
+    }
+}
+
+impl Diagnostic {
+    // Одна строка JSON для редакторов и CI:
+    // {"code":"H0405","severity":"error","message":"...","file":"main.hmr",
+    //  "span":{"line":2,"col":12,"end_line":2,"end_col":17},"label":null,"notes":[]}
+    // Без места в исходном коде file и span равны null
+    pub fn to_json(&self) -> String {
+        let (file, span) = match &self.loc {
+            Some(loc) => (
+                json_string(loc.file()),
+                format!(
+                    "{{\"line\":{},\"col\":{},\"end_line\":{},\"end_col\":{}}}",
+                    loc.line(),
+                    loc.col(),
+                    loc.end_line(),
+                    loc.end_col()
+                ),
+            ),
+            None => (String::from("null"), String::from("null")),
+        };
+        let code = self.code.map(json_string).unwrap_or(String::from("null"));
+        let label = self
+            .label
+            .as_deref()
+            .map(json_string)
+            .unwrap_or(String::from("null"));
+        let notes: Vec<String> = self.notes.iter().map(|n| json_string(n)).collect();
+        format!(
+            "{{\"code\":{code},\"severity\":\"error\",\"message\":{},\"file\":{file},\"span\":{span},\"label\":{label},\"notes\":[{}]}}",
+            json_string(&self.message),
+            notes.join(",")
+        )
+    }
+}
+
+fn json_string(s: &str) -> String {
+    let mut out = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"' => out += "\\\"",
+            '\\' => out += "\\\\",
+            '\n' => out += "\\n",
+            '\r' => out += "\\r",
+            '\t' => out += "\\t",
+            c if (c as u32) < 0x20 => out += &format!("\\u{:04x}", c as u32),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+// Вид, в котором командная строка выводит сообщения об ошибках
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MessageFormat {
+    // Текст с фрагментами исходного кода (Renderer)
+    Human,
+    // Каждое сообщение - отдельная строка JSON (Diagnostic::to_json)
+    Json,
+}
+
+impl MessageFormat {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "human" => Some(Self::Human),
+            "json" => Some(Self::Json),
+            _ => None,
+        }
+    }
+}
+
+pub trait ToDiagnostic {
+    fn diagnostic(&self) -> Diagnostic;
+}
+
+const RED: &str = "\x1b[1;31m";
+const BLUE: &str = "\x1b[1;34m";
+const BOLD: &str = "\x1b[1m";
+const RESET: &str = "\x1b[0m";
+
+// Выводит сообщения вместе со строкой исходного кода, в которой
+// подчеркнут участок с ошибкой:
+//
+// ошибка[H0405]: деление на 0
+//  --> main.hmr:2:12
+//   |
+// 2 |     return a / b;
+//   |            ^^^^^
+//   = пояснение
+pub struct Renderer {
+    // Исходный код по именам файлов. Файлы, которых здесь нет, читаются с диска
+    sources: HashMap<String, Option<String>>,
+    color: bool,
+}
+
+impl Renderer {
+    pub fn new(color: bool) -> Self {
+        Self {
+            sources: HashMap::new(),
+            color,
+        }
+    }
+
+    // Сообщения выводятся в stderr. Цвет включается, только если он
+    // выводится в терминал и не задана переменная окружения NO_COLOR
+    pub fn for_stderr() -> Self {
+        Self::new(io::stderr().is_terminal() && env::var_os("NO_COLOR").is_none())
+    }
+
+    pub fn add_source(&mut self, file: &str, source: &str) {
+        self.sources
+            .insert(file.to_string(), Some(source.to_string()));
+    }
+
+    pub fn render(&mut self, diagnostic: &Diagnostic) -> String {
+        let code = diagnostic
+            .code
+            .map(|code| format!("[{code}]"))
+            .unwrap_or_default();
+        let mut out = format!(
+            "{}{}{code}{}: {}{}{}",
+            self.paint(RED),
+            tr!("ошибка", "error"),
+            self.paint(RESET),
+            self.paint(BOLD),
+            diagnostic.message,
+            self.paint(RESET)
+        );
+        let mut gutter = String::from(" ");
+        if let Some(loc) = &diagnostic.loc {
+            gutter = " ".repeat(loc.line().to_string().len());
+            out += &format!(
+                "\n{gutter}{}-->{} {}:{}:{}",
+                self.paint(BLUE),
+                self.paint(RESET),
+                loc.file(),
+                loc.line(),
+                loc.col()
+            );
+            let line = self.source_line(loc);
+            if let Some(snippet) =
+                line.and_then(|line| self.snippet(loc, &line, &gutter, diagnostic.label.as_deref()))
+            {
+                out += &snippet;
+            }
+        }
+        for note in &diagnostic.notes {
+            let mut lines = note.lines();
+            let first = lines.next().unwrap_or_default();
+            out += &format!(
+                "\n{gutter} {}={} {first}",
+                self.paint(BLUE),
+                self.paint(RESET)
+            );
+            for line in lines {
+                out += &format!("\n{gutter}   {line}");
+            }
+        }
+        out
+    }
+
+    // None, если место указано неверно (например, в файле байткода)
+    fn snippet(&self, loc: &Loc, line: &str, gutter: &str, label: Option<&str>) -> Option<String> {
+        // Табуляции в отступе сохраняются, чтобы подчеркивание совпало с текстом
+        let indent: String = line
+            .chars()
+            .take(loc.col().checked_sub(1)?)
+            .map(|c| if c == '\t' { '\t' } else { ' ' })
+            .collect();
+        let line_len = line.chars().count();
+        let end_col = if loc.end_line() == loc.line() {
+            loc.end_col().min(line_len + 1)
+        } else {
+            line_len + 1
+        };
+        let carets = "^".repeat(end_col.saturating_sub(loc.col()).max(1));
+        let label = label.map(|l| format!(" {l}")).unwrap_or_default();
+        let bar = format!("{}|{}", self.paint(BLUE), self.paint(RESET));
+        Some(format!(
+            "\n{gutter} {bar}\n{}{} {bar} {line}\n{gutter} {bar} {indent}{}{carets}{label}{}",
+            self.paint(BLUE),
+            loc.line(),
+            self.paint(RED),
+            self.paint(RESET)
+        ))
+    }
+
+    fn source_line(&mut self, loc: &Loc) -> Option<String> {
+        let source = self
+            .sources
+            .entry(loc.file().to_string())
+            .or_insert_with(|| fs::read_to_string(loc.file()).ok());
+        source
+            .as_ref()?
+            .lines()
+            .nth(loc.line().checked_sub(1)?)
+            .map(String::from)
+    }
+
+    fn paint(&self, code: &'static str) -> &'static str {
+        if self.color {
+            code
+        } else {
+            ""
+        }
+    }
+}
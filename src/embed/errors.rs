@@ -0,0 +1,46 @@
+use crate::diagnostics::{Diagnostic, ToDiagnostic};
+use crate::locale::tr;
+use std::fmt;
+
+#[derive(Debug)]
+pub enum EmbedError {
+    UnknownGlobal(String),
+    InvalidHostName(String),
+    TooManyHostFunctions,
+}
+
+impl EmbedError {
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::UnknownGlobal(_) => "H0601",
+            Self::InvalidHostName(_) => "H0602",
+            Self::TooManyHostFunctions => "H0603",
+        }
+    }
+}
+
+impl ToDiagnostic for EmbedError {
+    fn diagnostic(&self) -> Diagnostic {
+        Diagnostic::new(self.to_string()).with_code(self.code())
+    }
+}
+
+impl fmt::Display for EmbedError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let message = match self {
+            Self::UnknownGlobal(name) => tr!(
+                "глобальная переменная {name} не объявлена",
+                "global variable {name} is not declared"
+            ),
+            Self::InvalidHostName(name) => tr!(
+                "{name} не может быть именем функции приложения: это не идентификатор либо это ключевое слово или имя встроенной функции",
+                "{name} cannot be a host function name: it is not an identifier, or it is a keyword or a builtin function name"
+            ),
+            Self::TooManyHostFunctions => tr!(
+                "зарегистрировано больше 65536 функций приложения",
+                "more than 65536 host functions are registered"
+            ),
+        };
+        write!(f, "{message}")
+    }
+}
@@ -0,0 +1,159 @@
+use crate::{
+    compile::compiler::Compiler,
+    embed::errors::EmbedError,
+    error::{ErrorKind, HammerError},
+    parser::{self, ast::Ast},
+    vm::{
+        host::HostFunctions,
+        verifier::Verifier,
+        vm::{Bytecode, Value, VM},
+    },
+};
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    io::{self, BufRead, Write},
+    rc::Rc,
+};
+
+// Скомпилированная программа вместе с номерами ее глобальных переменных
+pub struct Program {
+    bytecode: Bytecode,
+    globals: HashMap<String, u32>,
+    host_functions: HostFunctions,
+}
+
+impl Program {
+    // name используется в сообщениях об ошибках вместо имени файла
+    pub fn compile(name: &str, source: &str) -> Result<Self, HammerError> {
+        Self::compile_with(name, source, &HostFunctions::new())
+    }
+
+    // Программа может вызывать функции приложения из host_functions
+    pub fn compile_with(
+        name: &str,
+        source: &str,
+        host_functions: &HostFunctions,
+    ) -> Result<Self, HammerError> {
+        let host_names = host_functions.iter().map(|f| f.name.clone());
+        let Ast { tree, variables } =
+            parser::parse(name.to_string(), source.to_string(), host_names)?;
+        let mut compiler = Compiler::new().with_host_functions(host_functions);
+        let bytecode = compiler
+            .compile(tree, variables)
+            .map_err(ErrorKind::Compile)?;
+        Ok(Self {
+            bytecode,
+            globals: compiler.globals(),
+            host_functions: host_functions.clone(),
+        })
+    }
+
+    // Программа из файла байткода, например созданного to_bytes. Байткод
+    // проверяется, HCALL в нем должны ссылаться на функции из host_functions.
+    // Имена глобальных переменных в файле не хранятся, поэтому Runtime::global
+    // и Runtime::set_global их не находят
+    pub fn from_bytes(bytes: &[u8], host_functions: &HostFunctions) -> Result<Self, HammerError> {
+        let bytecode = Bytecode::from_bytes(bytes).map_err(ErrorKind::Bytecode)?;
+        Verifier::new(&bytecode)
+            .with_host_functions(host_functions)
+            .verify()
+            .map_err(ErrorKind::Bytecode)?;
+        Ok(Self {
+            bytecode,
+            globals: HashMap::new(),
+            host_functions: host_functions.clone(),
+        })
+    }
+
+    // Байткод в формате файла, его можно запустить через hammer run -b
+    pub fn to_bytes(&self) -> Result<Vec<u8>, HammerError> {
+        let bytes = self.bytecode.to_bytes().map_err(ErrorKind::Bytecode)?;
+        Ok(bytes)
+    }
+
+    pub fn globals(&self) -> impl Iterator<Item = &str> {
+        self.globals.keys().map(String::as_str)
+    }
+}
+
+// Виртуальная машина с загруженной программой. Значения глобальных
+// переменных сохраняются между запусками
+pub struct Runtime {
+    vm: VM,
+    globals: HashMap<String, u32>,
+}
+
+impl Runtime {
+    pub fn new(program: Program) -> Self {
+        let mut vm = VM::from_bytecode(program.bytecode);
+        vm.set_host_functions(program.host_functions);
+        Self {
+            vm,
+            globals: program.globals,
+        }
+    }
+
+    // Вывод println направляется в output вместо стандартного вывода
+    pub fn with_output(mut self, output: impl Write + 'static) -> Self {
+        self.vm.set_output(Box::new(output));
+        self
+    }
+
+    // readln читает строки из input вместо стандартного ввода
+    pub fn with_input(mut self, input: impl BufRead + 'static) -> Self {
+        self.vm.set_input(Box::new(input));
+        self
+    }
+
+    pub fn run(&mut self) -> Result<(), HammerError> {
+        self.vm.run_from(0).map_err(ErrorKind::Interp)?;
+        Ok(())
+    }
+
+    // Код, переданный в exit при последнем запуске. None, если программа
+    // завершилась без вызова exit
+    pub fn exit_code(&self) -> Option<u8> {
+        self.vm.exit_code()
+    }
+
+    // None, если переменная не объявлена или еще не получила значения
+    pub fn global(&self, name: &str) -> Option<Value> {
+        let idx = *self.globals.get(name)?;
+        self.vm.global(idx as usize).cloned()
+    }
+
+    pub fn set_global(&mut self, name: &str, value: Value) -> Result<(), HammerError> {
+        let idx = *self
+            .globals
+            .get(name)
+            .ok_or_else(|| ErrorKind::Embed(EmbedError::UnknownGlobal(name.to_string())))?;
+        self.vm.set_global(idx as usize, value);
+        Ok(())
+    }
+}
+
+// Буфер для перехвата вывода программы: клон передается в
+// Runtime::with_output, а содержимое читается через contents
+#[derive(Clone, Default)]
+pub struct CapturedOutput(Rc<RefCell<Vec<u8>>>);
+
+impl CapturedOutput {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn contents(&self) -> String {
+        String::from_utf8_lossy(&self.0.borrow()).into_owned()
+    }
+}
+
+impl Write for CapturedOutput {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.borrow_mut().write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
@@ -1,28 +1,86 @@
+use crate::asm::errors::*;
 use crate::cli::errors::*;
 use crate::compile::errors::*;
+use crate::diagnostics::{Diagnostic, ToDiagnostic};
+use crate::embed::errors::*;
 use crate::parser::errors::*;
 use crate::vm::errors::*;
 
 use std::fmt;
 
-pub enum HammerError {
+// Источник ошибки и ее содержимое. Используется только внутри библиотеки
+#[derive(Debug)]
+pub(crate) enum ErrorKind {
     Cli(CliError),
     Compile(CompileError),
-    Lex(LexError),
-    Parse(ParseError),
-    Interp(InterpretationError),
+    // Ошибки лексера и ошибки парсера в оставшихся лексемах
+    Lex(Vec<LexError>, Vec<ParseError>),
+    Parse(Vec<ParseError>),
+    Asm(Vec<AsmError>),
+    Interp(RuntimeError),
     Bytecode(BytecodeError),
+    Embed(EmbedError),
+}
+
+// Ошибка Hammer. Содержимое скрыто: снаружи доступны код ошибки,
+// код завершения и текст сообщения
+#[derive(Debug)]
+pub struct HammerError(ErrorKind);
+
+impl From<ErrorKind> for HammerError {
+    fn from(kind: ErrorKind) -> Self {
+        Self(kind)
+    }
+}
+
+impl HammerError {
+    // Код первой ошибки (например, H0405), остальные коды есть в тексте сообщения
+    pub fn code(&self) -> &'static str {
+        self.diagnostics()
+            .first()
+            .and_then(|diagnostic| diagnostic.code)
+            .expect("Невозможная ситуация: у ошибки нет кода")
+    }
+
+    // Код завершения процесса для каждого вида ошибок. Программа может
+    // задать свой код завершения через exit
+    pub fn exit_code(&self) -> u8 {
+        match &self.0 {
+            ErrorKind::Cli(CliError::ReadFailed(..)) => 9,
+            ErrorKind::Cli(_) => 2,
+            ErrorKind::Lex(..) => 3,
+            ErrorKind::Parse(_) => 4,
+            ErrorKind::Compile(_) | ErrorKind::Asm(_) => 5,
+            ErrorKind::Bytecode(_) => 6,
+            ErrorKind::Interp(_) => 7,
+            ErrorKind::Embed(_) => 8,
+        }
+    }
+
+    // Лексер, парсер и ассемблер сообщают сразу обо всех найденных ошибках
+    pub(crate) fn diagnostics(&self) -> Vec<Diagnostic> {
+        match &self.0 {
+            ErrorKind::Cli(e) => vec![e.diagnostic()],
+            ErrorKind::Compile(e) => vec![e.diagnostic()],
+            ErrorKind::Lex(lex, parse) => lex
+                .iter()
+                .map(ToDiagnostic::diagnostic)
+                .chain(parse.iter().map(ToDiagnostic::diagnostic))
+                .collect(),
+            ErrorKind::Parse(errors) => errors.iter().map(ToDiagnostic::diagnostic).collect(),
+            ErrorKind::Asm(errors) => errors.iter().map(ToDiagnostic::diagnostic).collect(),
+            ErrorKind::Interp(e) => vec![e.diagnostic()],
+            ErrorKind::Bytecode(e) => vec![e.diagnostic()],
+            ErrorKind::Embed(e) => vec![e.diagnostic()],
+        }
+    }
 }
 
 impl fmt::Display for HammerError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        match self {
-            Self::Cli(e) => write!(f, "{e}"),
-            Self::Compile(e) => write!(f, "{e}"),
-            Self::Lex(e) => write!(f, "{e}"),
-            Self::Parse(e) => write!(f, "{e}"),
-            Self::Interp(e) => write!(f, "{e}"),
-            Self::Bytecode(e) => write!(f, "{e}"),
-        }
+        let diagnostics: Vec<String> = self.diagnostics().iter().map(|d| d.to_string()).collect();
+        write!(f, "{}", diagnostics.join("\n"))
     }
 }
+
+impl std::error::Error for HammerError {}
@@ -0,0 +1,19 @@
+mod asm;
+mod cli;
+mod compile;
+mod diagnostics;
+mod embed;
+mod error;
+mod locale;
+mod parser;
+mod utils;
+mod vm;
+
+pub use embed::program::{CapturedOutput, Program, Runtime};
+pub use error::HammerError;
+pub use vm::host::{HostFn, HostFunction, HostFunctions};
+pub use vm::vm::Value;
+
+// Точка входа исполняемого файла hammer, не часть API
+#[doc(hidden)]
+pub use cli::main as run_cli;
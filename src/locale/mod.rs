@@ -0,0 +1,72 @@
+use std::{
+    env,
+    sync::atomic::{AtomicU8, Ordering},
+};
+
+// Язык сообщений компилятора, виртуальной машины и командной строки
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Lang {
+    Ru,
+    En,
+}
+
+// 0 - язык еще не выбран
+static CURRENT: AtomicU8 = AtomicU8::new(0);
+
+impl Lang {
+    // Принимает как короткие имена (ru, en), так и значения вида ru_RU.UTF-8
+    pub fn parse(name: &str) -> Option<Self> {
+        let lang = name.split(['_', '.', '-', '@']).next()?;
+        match lang.to_ascii_lowercase().as_str() {
+            "ru" => Some(Self::Ru),
+            "en" => Some(Self::En),
+            _ => None,
+        }
+    }
+
+    // Язык из переменной окружения HAMMER_LANG, а если она не задана
+    // или неизвестна, то из LANG: русский для русской локали (ru_RU.UTF-8),
+    // английский для любой другой (C.UTF-8, de_DE.UTF-8) и без локали
+    pub fn from_env() -> Self {
+        let var = |name| env::var(name).ok().and_then(|value| Self::parse(&value));
+        match var("HAMMER_LANG") {
+            Some(lang) => lang,
+            None => var("LANG").unwrap_or(Self::En),
+        }
+    }
+
+    // Пока язык не выбран явно, он берется из окружения
+    pub fn current() -> Self {
+        match CURRENT.load(Ordering::Relaxed) {
+            1 => Self::Ru,
+            2 => Self::En,
+            _ => {
+                let lang = Self::from_env();
+                lang.set();
+                lang
+            }
+        }
+    }
+
+    pub fn set(self) {
+        let n = match self {
+            Self::Ru => 1,
+            Self::En => 2,
+        };
+        CURRENT.store(n, Ordering::Relaxed);
+    }
+}
+
+// Сообщение на текущем языке. Оба варианта - строки форматирования,
+// которые могут ссылаться на переменные в месте вызова:
+// tr!("ожидалось {n}", "expected {n}")
+macro_rules! tr {
+    ($ru:literal, $en:literal) => {
+        match $crate::locale::Lang::current() {
+            $crate::locale::Lang::Ru => format!($ru),
+            $crate::locale::Lang::En => format!($en),
+        }
+    };
+}
+
+pub(crate) use tr;
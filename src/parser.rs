@@ -2,3 +2,28 @@ pub mod ast;
 pub mod errors;
 pub mod lexer;
 pub mod tokens;
+
+use crate::error::{ErrorKind, HammerError};
+use ast::{Ast, AstBuilder};
+use lexer::Lexer;
+
+// Лексический и синтаксический разбор файла. Лексер пропускает ошибочные
+// лексемы, поэтому парсер разбирает оставшиеся, и обо всех ошибках
+// сообщается сразу. functions - имена функций приложения
+pub fn parse(
+    file: String,
+    source: String,
+    functions: impl IntoIterator<Item = String>,
+) -> Result<Ast, HammerError> {
+    let mut lexer = Lexer::new(file, source);
+    let lexed = lexer.lex();
+    let mut ast_builder = AstBuilder::new(lexer.tokens().to_vec()).with_functions(functions);
+    let parsed = ast_builder.parse();
+    match (lexed, parsed) {
+        (Ok(()), Ok(())) => Ok(ast_builder.ast()),
+        (Ok(()), Err(errors)) => Err(ErrorKind::Parse(errors).into()),
+        (Err(errors), parsed) => {
+            Err(ErrorKind::Lex(errors, parsed.err().unwrap_or_default()).into())
+        }
+    }
+}
@@ -1,4 +1,4 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
 use crate::parser::errors::*;
 use crate::parser::tokens::*;
@@ -9,11 +9,21 @@ pub enum Stmt {
     Expr(Box<Expr>),
     Decl(Variable, Option<Box<Expr>>),
     Reassign(Variable, Box<Expr>),
+    // Условие, ветка then (блок), ветка else (блок или следующий if)
+    If(Box<Expr>, Box<Self>, Option<Box<Self>>),
+    While(Box<Expr>, Box<Self>),
+    // Переменная цикла, начало и конец диапазона (не включая), тело
+    For(Variable, Box<Expr>, Box<Expr>, Box<Self>),
+    Break(Loc),
+    Continue(Loc),
+    Fn(Function, Box<Self>),
+    Return(Option<Box<Expr>>, Loc),
 }
 
 #[derive(Clone, Debug)]
 pub enum Expr {
-    Func(Token, Box<Self>),
+    // Встроенная (Token::Builtin) или определенная (Token::Ident) функция и аргументы
+    Func(Token, Vec<Self>),
     Literal(Token),
     Grouping(Box<Self>),
     Binary(Box<Self>, Token, Box<Self>),
@@ -22,16 +32,48 @@ pub enum Expr {
     None,
 }
 
+impl Expr {
+    // Участок исходного кода, занимаемый выражением
+    pub fn span(&self) -> Loc {
+        match self {
+            Self::Func(func, _) => func.loc.clone(),
+            Self::Literal(token) => token.loc.clone(),
+            Self::Grouping(expr) => expr.span(),
+            Self::Binary(left, _, right) => left.span().to(&right.span()),
+            Self::Unary(op, expr) => op.loc.to(&expr.span()),
+            Self::Variable(_, loc) => loc.clone(),
+            Self::None => panic!("неожиданное появление AstNode::None"),
+        }
+    }
+}
+
 #[derive(Clone, Debug, PartialEq, Eq, Hash)]
 pub struct Variable {
     pub name: String,
+    // Уникальный номер объявления: переменные с одним именем
+    // из разных областей видимости различаются
+    pub id: usize,
+}
+
+#[derive(Clone, Debug)]
+pub struct Function {
+    pub name: String,
+    pub params: Vec<Variable>,
+    pub loc: Loc,
 }
 
+#[derive(Clone)]
 pub struct AstBuilder {
     tokens: Vec<Token>,
     cursor: usize,
     tree: Vec<Stmt>,
     variables: Vec<Variable>,
+    functions: Vec<String>,
+    // Области видимости от внешней (глобальной) к текущей
+    scopes: Vec<Vec<Variable>>,
+    // Имена переменных, области видимости которых уже закончились
+    out_of_scope: HashSet<String>,
+    errors: Vec<ParseError>,
 }
 
 pub struct Ast {
@@ -46,9 +88,19 @@ impl AstBuilder {
             cursor: 0,
             tree: vec![],
             variables: vec![],
+            functions: vec![],
+            scopes: vec![vec![]],
+            out_of_scope: HashSet::new(),
+            errors: vec![],
         }
     }
 
+    // Функции, объявленные вне программы (функции приложения)
+    pub fn with_functions(mut self, names: impl IntoIterator<Item = String>) -> Self {
+        self.functions.extend(names);
+        self
+    }
+
     pub fn ast(self) -> Ast {
         Ast {
             tree: self.tree,
@@ -56,59 +108,343 @@ impl AstBuilder {
         }
     }
 
-    pub fn parse(&mut self) -> Result<(), ParseError> {
-        loop {
-            let stmt;
+    // После ошибки разбор продолжается со следующей инструкции,
+    // так что за один проход находятся все ошибки
+    pub fn parse(&mut self) -> Result<(), Vec<ParseError>> {
+        while !self.at_eof() {
+            let start = self.cursor;
+            match self.stmt() {
+                Ok(stmt) => self.tree.push(stmt),
+                Err(e) => self.recover(e, start),
+            }
+        }
+        if self.errors.is_empty() {
+            Ok(())
+        } else {
+            Err(std::mem::take(&mut self.errors))
+        }
+    }
+
+    // Разбирает очередную часть программы (например, строку REPL).
+    // Объявленные ранее переменные и функции остаются видны
+    pub fn parse_more(&mut self, tokens: Vec<Token>) -> Result<Vec<Stmt>, Vec<ParseError>> {
+        self.tokens = tokens;
+        self.cursor = 0;
+        self.parse()?;
+        Ok(std::mem::take(&mut self.tree))
+    }
+
+    fn stmt(&mut self) -> Result<Stmt, ParseError> {
+        let stmt = match &self.peek()?.ttype {
+            TokenType::Keyword(Kw::If) => {
+                self.consume()?;
+                return self.if_stmt();
+            }
+            TokenType::Keyword(Kw::While) => {
+                self.consume()?;
+                let cond = self.expr()?;
+                let body = self.braced_block()?;
+                return Ok(Stmt::While(Box::new(cond), Box::new(body)));
+            }
+            TokenType::Keyword(Kw::For) => {
+                self.consume()?;
+                return self.for_stmt();
+            }
+            TokenType::BraceLeft => {
+                self.consume()?;
+                return Ok(Stmt::Block(self.block()?));
+            }
+            TokenType::Keyword(Kw::Fn) => {
+                self.consume()?;
+                return self.fn_decl();
+            }
+            TokenType::Keyword(Kw::Return) => {
+                let loc = self.consume()?.loc.clone();
+                let expr = if self.check_ttype(&TokenType::Semicolon)? {
+                    None
+                } else {
+                    Some(Box::new(self.expr()?))
+                };
+                Stmt::Return(expr, loc)
+            }
+            TokenType::Keyword(Kw::Break) => Stmt::Break(self.consume()?.loc.clone()),
+            TokenType::Keyword(Kw::Continue) => Stmt::Continue(self.consume()?.loc.clone()),
+            TokenType::Keyword(Kw::Let) => {
+                self.consume()?;
+                self.decl()?
+            }
+            TokenType::Ident(_) if self.peek_next()?.ttype == TokenType::Assign => {
+                self.reassign()?
+            }
+            _ => Stmt::Expr(Box::new(self.expr()?)),
+        };
+        if !self.match_ttype(&[TokenType::Semicolon])? {
+            return Err(ParseError::ExpectedSemi(self.prev().loc.clone()));
+        }
+        Ok(stmt)
+    }
+
+    // Открывающая фигурная скобка уже поглощена
+    fn block(&mut self) -> Result<Vec<Stmt>, ParseError> {
+        self.begin_scope();
+        let mut stmts = vec![];
+        let result = loop {
             match &self.peek()?.ttype {
-                TokenType::Eof => break,
-                TokenType::Keyword(Kw::Let) => {
+                TokenType::BraceRight => {
                     self.consume()?;
-                    stmt = self.decl()?;
-                    self.tree.push(stmt);
+                    break Ok(stmts);
                 }
-                // Здесь вполне возможен вызов определенной функции.
-                // Хоть сейчас и не поддерживается такое,
-                // я хочу сейчас обеспечить поддержку этого
-                TokenType::Ident(id) => {
-                    if self.peek()?.ttype == TokenType::ParenLeft {
-                        stmt = Stmt::Expr(Box::new(self.expr()?));
-                        self.tree.push(stmt);
-                    } else {
-                        stmt = self.reassign()?;
-                        self.tree.push(stmt);
+                TokenType::Eof => break Err(ParseError::UnexpectedEof(self.peek()?.loc.clone())),
+                _ => {
+                    let start = self.cursor;
+                    match self.stmt() {
+                        Ok(stmt) => stmts.push(stmt),
+                        Err(e) => self.recover(e, start),
                     }
                 }
-                _ => {
-                    stmt = Stmt::Expr(Box::new(self.expr()?));
-                    self.tree.push(stmt);
+            }
+        };
+        self.end_scope();
+        result
+    }
+
+    // Запоминает ошибку и пропускает токены до конца инструкции, в которой
+    // она случилась: до точки с запятой или до конца блока. start - позиция
+    // начала инструкции
+    fn recover(&mut self, error: ParseError, start: usize) {
+        // Незакрытые блоки сообщают о конце файла каждый по отдельности
+        let repeated_eof = self.at_eof()
+            && matches!(error, ParseError::UnexpectedEof(_))
+            && matches!(self.errors.last(), Some(ParseError::UnexpectedEof(_)));
+        if !repeated_eof {
+            self.errors.push(error);
+        }
+        if self.cursor == start {
+            self.cursor += 1;
+        }
+        let mut depth = 0;
+        loop {
+            match &self.tokens[self.cursor].ttype {
+                TokenType::Eof => return,
+                TokenType::Semicolon if depth == 0 => {
+                    self.cursor += 1;
+                    return;
+                }
+                TokenType::BraceLeft => depth += 1,
+                TokenType::BraceRight if depth == 0 => return,
+                TokenType::BraceRight => {
+                    depth -= 1;
+                    if depth == 0 {
+                        self.cursor += 1;
+                        return;
+                    }
                 }
+                TokenType::Keyword(
+                    Kw::Let | Kw::If | Kw::While | Kw::For | Kw::Fn | Kw::Return,
+                ) if depth == 0 => return,
+                _ => {}
+            }
+            self.cursor += 1;
+        }
+    }
+
+    // Ключевое слово if уже поглощено
+    fn if_stmt(&mut self) -> Result<Stmt, ParseError> {
+        let cond = self.expr()?;
+        let then_branch = self.braced_block()?;
+        let else_branch = if self.match_ttype(&[TokenType::Keyword(Kw::Else)])? {
+            if self.match_ttype(&[TokenType::Keyword(Kw::If)])? {
+                Some(Box::new(self.if_stmt()?))
+            } else {
+                Some(Box::new(self.braced_block()?))
+            }
+        } else {
+            None
+        };
+        Ok(Stmt::If(Box::new(cond), Box::new(then_branch), else_branch))
+    }
+
+    // Ключевое слово for уже поглощено
+    fn for_stmt(&mut self) -> Result<Stmt, ParseError> {
+        let token = self.consume()?.clone();
+        let TokenType::Ident(name) = &token.ttype else {
+            return Err(ParseError::ExpectedIdent(token.loc.clone()));
+        };
+        if !self.match_ttype(&[TokenType::Keyword(Kw::In)])? {
+            return Err(ParseError::ExpectedIn(self.peek()?.loc.clone()));
+        }
+        let start = self.expr()?;
+        if !self.match_ttype(&[TokenType::DotDot])? {
+            return Err(ParseError::ExpectedRange(self.peek()?.loc.clone()));
+        }
+        let end = self.expr()?;
+        // Переменная цикла видна только в его теле
+        self.begin_scope();
+        let var = self.declare(name);
+        let body = self.braced_block();
+        self.end_scope();
+        let body = body?;
+        Ok(Stmt::For(
+            var,
+            Box::new(start),
+            Box::new(end),
+            Box::new(body),
+        ))
+    }
+
+    // Ключевое слово fn уже поглощено
+    fn fn_decl(&mut self) -> Result<Stmt, ParseError> {
+        let loc = self.prev().loc.clone();
+        if self.scopes.len() > 1 {
+            return Err(ParseError::NestedFunction(loc));
+        }
+        let token = self.consume()?.clone();
+        let TokenType::Ident(name) = &token.ttype else {
+            return Err(ParseError::ExpectedIdent(token.loc.clone()));
+        };
+        if self.functions.contains(name) {
+            return Err(ParseError::DuplicateFunction(token.loc.clone()));
+        }
+        // Функция объявляется до разбора тела, чтобы она могла вызывать сама себя.
+        // При ошибке в объявлении имя тоже остается известным, чтобы ее вызовы
+        // не давали лишних ошибок
+        self.functions.push(name.to_string());
+        if !self.match_ttype(&[TokenType::ParenLeft])? {
+            return Err(ParseError::ExpectedParen(self.peek()?.loc.clone()));
+        }
+        self.begin_scope();
+        let params = self.params();
+        let body = params.and_then(|params| Ok((params, self.braced_block()?)));
+        self.end_scope();
+        let (params, body) = body?;
+        let function = Function {
+            name: name.to_string(),
+            params,
+            loc,
+        };
+        Ok(Stmt::Fn(function, Box::new(body)))
+    }
+
+    // Параметры функции в скобках через запятую
+    fn params(&mut self) -> Result<Vec<Variable>, ParseError> {
+        let mut params: Vec<Variable> = vec![];
+        if self.match_ttype(&[TokenType::ParenRight])? {
+            return Ok(params);
+        }
+        loop {
+            let param = self.peek()?.clone();
+            let TokenType::Ident(param_name) = &param.ttype else {
+                return Err(ParseError::ExpectedIdent(param.loc.clone()));
+            };
+            self.consume()?;
+            if params.iter().any(|p| p.name == *param_name) {
+                return Err(ParseError::DuplicateParam(param.loc.clone()));
             }
-            if !self.match_ttype(&[TokenType::Semicolon])? {
-                return Err(ParseError::ExpectedSemi(self.prev().loc.clone()));
+            params.push(self.declare(param_name));
+            if self.match_ttype(&[TokenType::ParenRight])? {
+                return Ok(params);
+            }
+            if !self.match_ttype(&[TokenType::Comma])? {
+                return Err(ParseError::ExpectedParen(self.peek()?.loc.clone()));
             }
         }
-        Ok(())
+    }
+
+    // Место вызова охватывает имя функции и все аргументы до закрывающей скобки
+    fn call(&mut self, func: &Token) -> Result<Expr, ParseError> {
+        let args = self.args()?;
+        let mut func = func.clone();
+        func.loc = func.loc.to(&self.prev().loc);
+        Ok(Expr::Func(func, args))
+    }
+
+    // Аргументы вызова функции в скобках через запятую
+    fn args(&mut self) -> Result<Vec<Expr>, ParseError> {
+        if !self.match_ttype(&[TokenType::ParenLeft])? {
+            return Err(ParseError::ExpectedParen(self.peek()?.loc.clone()));
+        }
+        let mut args = vec![];
+        if self.match_ttype(&[TokenType::ParenRight])? {
+            return Ok(args);
+        }
+        loop {
+            args.push(self.expr()?);
+            if self.match_ttype(&[TokenType::ParenRight])? {
+                return Ok(args);
+            }
+            if !self.match_ttype(&[TokenType::Comma])? {
+                return Err(ParseError::ExpectedParen(self.peek()?.loc.clone()));
+            }
+        }
+    }
+
+    fn begin_scope(&mut self) {
+        self.scopes.push(vec![]);
+    }
+
+    fn end_scope(&mut self) {
+        let scope = self
+            .scopes
+            .pop()
+            .expect("Глобальная область видимости никогда не закрывается");
+        for var in scope {
+            self.out_of_scope.insert(var.name);
+        }
+    }
+
+    fn declare(&mut self, name: &str) -> Variable {
+        let var = Variable {
+            name: name.to_string(),
+            id: self.variables.len(),
+        };
+        self.variables.push(var.clone());
+        self.scopes
+            .last_mut()
+            .expect("Глобальная область видимости никогда не закрывается")
+            .push(var.clone());
+        var
+    }
+
+    // Ищет переменную от текущей области видимости к глобальной,
+    // так что внутренние объявления перекрывают внешние
+    fn lookup(&self, name: &str, loc: &Loc) -> Result<Variable, ParseError> {
+        let found = self
+            .scopes
+            .iter()
+            .rev()
+            .flat_map(|scope| scope.iter().rev())
+            .find(|var| var.name == name);
+        match found {
+            Some(var) => Ok(var.clone()),
+            None if self.out_of_scope.contains(name) => {
+                Err(ParseError::OutOfScopeVariable(loc.clone()))
+            }
+            None => Err(ParseError::UnknownVariable(loc.clone())),
+        }
+    }
+
+    fn braced_block(&mut self) -> Result<Stmt, ParseError> {
+        if !self.match_ttype(&[TokenType::BraceLeft])? {
+            return Err(ParseError::ExpectedBrace(self.peek()?.loc.clone()));
+        }
+        Ok(Stmt::Block(self.block()?))
     }
 
     fn decl(&mut self) -> Result<Stmt, ParseError> {
-        
         let token = &self.consume()?.clone();
         let name = match &token.ttype {
             TokenType::Ident(id) => id,
             _ => return Err(ParseError::ExpectedIdent(self.prev().loc.clone())),
         };
+        // Выражение разбирается до объявления, поэтому в let x = x + 1
+        // справа используется внешняя переменная x. Переменная объявляется
+        // и при ошибке в выражении, чтобы ее использование не давало лишних ошибок
         if self.match_ttype(&[TokenType::Assign])? {
-            let expr = self.expr()?;
-            let var = Variable {
-                name: name.to_string(),
-            };
-            self.variables.push(var.clone());
-            Ok(Stmt::Decl(var, Some(Box::new(expr))))
+            let expr = self.expr();
+            let var = self.declare(name);
+            Ok(Stmt::Decl(var, Some(Box::new(expr?))))
         } else {
-            let var = Variable {
-                name: name.to_string(),
-            };
-            self.variables.push(var.clone());
+            let var = self.declare(name);
             Ok(Stmt::Decl(var, None))
         }
     }
@@ -125,31 +461,71 @@ impl AstBuilder {
         if !self.match_ttype(&[TokenType::Assign])? {
             return Err(ParseError::ExpectedAssign(self.prev().loc.clone()));
         }
-        
-        let found_var = self.variables.iter().find(|var| var.name == *varname);
-        let var = match found_var {
-            None => return Err(ParseError::UnknownVariable(loc.clone())),
-            Some(v) => v.clone(),
-        };
+
+        let var = self.lookup(varname, loc)?;
         let expr = self.expr()?;
         Ok(Stmt::Reassign(var, Box::new(expr)))
     }
 
     fn expr(&mut self) -> Result<Expr, ParseError> {
-        match &self.peek()?.ttype {
-            TokenType::Builtin(_) => {
-                let func = self.consume()?.clone();
-                if !self.match_ttype(&[TokenType::ParenLeft])? {
-                    return Err(ParseError::ExpectedParen(self.peek()?.loc.clone()));
-                }
-                let expr = self.expr()?;
-                if !self.match_ttype(&[TokenType::ParenRight])? {
-                    return Err(ParseError::ExpectedParen(self.peek()?.loc.clone()));
-                }
-                Ok(Expr::Func(func, Box::new(expr)))
-            }
-            _ => self.term(),
+        self.logic_or()
+    }
+
+    fn logic_or(&mut self) -> Result<Expr, ParseError> {
+        let mut expr = self.logic_and()?;
+
+        while self.match_ttype(&[TokenType::OpOr])? {
+            let op = self.prev().clone();
+            let right = self.logic_and()?;
+
+            expr = Expr::Binary(Box::new(expr), op, Box::new(right));
         }
+
+        Ok(expr)
+    }
+
+    fn logic_and(&mut self) -> Result<Expr, ParseError> {
+        let mut expr = self.equality()?;
+
+        while self.match_ttype(&[TokenType::OpAnd])? {
+            let op = self.prev().clone();
+            let right = self.equality()?;
+
+            expr = Expr::Binary(Box::new(expr), op, Box::new(right));
+        }
+
+        Ok(expr)
+    }
+
+    fn equality(&mut self) -> Result<Expr, ParseError> {
+        let mut expr = self.comparison()?;
+
+        while self.match_ttype(&[TokenType::OpEq, TokenType::OpNeq])? {
+            let op = self.prev().clone();
+            let right = self.comparison()?;
+
+            expr = Expr::Binary(Box::new(expr), op, Box::new(right));
+        }
+
+        Ok(expr)
+    }
+
+    fn comparison(&mut self) -> Result<Expr, ParseError> {
+        let mut expr = self.term()?;
+
+        while self.match_ttype(&[
+            TokenType::OpLess,
+            TokenType::OpGreater,
+            TokenType::OpLessEq,
+            TokenType::OpGreaterEq,
+        ])? {
+            let op = self.prev().clone();
+            let right = self.term()?;
+
+            expr = Expr::Binary(Box::new(expr), op, Box::new(right));
+        }
+
+        Ok(expr)
     }
 
     fn term(&mut self) -> Result<Expr, ParseError> {
@@ -179,9 +555,9 @@ impl AstBuilder {
     }
 
     fn unary(&mut self) -> Result<Expr, ParseError> {
-        if self.match_ttype(&[TokenType::OpMinus])? {
+        if self.match_ttype(&[TokenType::OpMinus, TokenType::OpNot])? {
             let op = self.prev().clone();
-            let expr = self.primary()?;
+            let expr = self.unary()?;
 
             return Ok(Expr::Unary(op, Box::new(expr)));
         }
@@ -189,12 +565,29 @@ impl AstBuilder {
         self.primary()
     }
 
+    // Неподходящий токен не поглощается: с него продолжится разбор после ошибки
     fn primary(&mut self) -> Result<Expr, ParseError> {
-        let token = &self.consume()?.clone();
+        let token = &self.peek()?.clone();
+        match &token.ttype {
+            TokenType::Eof => return Err(ParseError::UnexpectedEof(token.loc.clone())),
+            TokenType::ParenRight => return Err(ParseError::UnmatchingBrace(token.loc.clone())),
+            TokenType::NumLit(_)
+            | TokenType::StrLit(_)
+            | TokenType::BoolLit(_)
+            | TokenType::Nil
+            | TokenType::Builtin(_)
+            | TokenType::ParenLeft
+            | TokenType::Ident(_) => {}
+            _ => return Err(ParseError::UnexpectedToken(token.loc.clone())),
+        }
+        self.consume()?;
 
         match &token.ttype {
-            TokenType::Eof => Err(ParseError::UnexpectedEof(token.loc.clone())),
-            TokenType::NumLit(value) => Ok(Expr::Literal(token.clone())),
+            TokenType::NumLit(_)
+            | TokenType::StrLit(_)
+            | TokenType::BoolLit(_)
+            | TokenType::Nil => Ok(Expr::Literal(token.clone())),
+            TokenType::Builtin(_) => self.call(token),
             TokenType::ParenLeft => {
                 let expr = self.expr()?;
                 if self.consume()?.ttype != TokenType::ParenRight {
@@ -202,15 +595,17 @@ impl AstBuilder {
                 }
                 Ok(Expr::Grouping(Box::new(expr)))
             }
-            TokenType::ParenRight => Err(ParseError::UnmatchingBrace(token.loc.clone())),
             TokenType::Ident(id) => {
-                let found_var = self.variables.iter().find(|var| var.name == *id);
-                match found_var {
-                    None => Err(ParseError::UnknownVariable(token.loc.clone())),
-                    Some(var) => Ok(Expr::Variable(var.clone(), token.loc.clone())),
+                if self.check_ttype(&TokenType::ParenLeft)? {
+                    if !self.functions.contains(id) {
+                        return Err(ParseError::UnknownFunction(token.loc.clone()));
+                    }
+                    return self.call(token);
                 }
+                let var = self.lookup(id, &token.loc)?;
+                Ok(Expr::Variable(var, token.loc.clone()))
             }
-            _ => Err(ParseError::UnexpectedToken(token.loc.clone())),
+            _ => unreachable!(),
         }
     }
 
@@ -246,10 +641,21 @@ impl AstBuilder {
             .ok_or_else(|| ParseError::UnexpectedEof(self.prev().loc.clone()))
     }
 
+    fn peek_next(&self) -> Result<&Token, ParseError> {
+        match self.tokens.get(self.cursor + 1) {
+            Some(token) => Ok(token),
+            None => Err(ParseError::UnexpectedEof(self.peek()?.loc.clone())),
+        }
+    }
+
     fn prev(&self) -> &Token {
         self.tokens.get(self.cursor - 1).unwrap()
     }
 
+    fn at_eof(&self) -> bool {
+        self.eof().unwrap_or(true)
+    }
+
     fn eof(&self) -> Result<bool, ParseError> {
         let ttype = &self.peek()?.ttype;
         Ok(*ttype == TokenType::Eof)
@@ -1,44 +1,183 @@
+use crate::diagnostics::{Diagnostic, ToDiagnostic};
+use crate::locale::tr;
 use crate::parser::tokens::Loc;
 use std::fmt;
 
+#[derive(Debug)]
 pub enum LexError {
     MalformedNumLit(Loc),
     UnknownLexem(Loc),
+    UnterminatedString(Loc),
+    InvalidEscape(Loc),
 }
 
-impl fmt::Display for LexError {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+impl LexError {
+    pub fn code(&self) -> &'static str {
         match self {
-            Self::MalformedNumLit(loc) => write!(f, "[{loc}] неправильный float литерал"),
-            Self::UnknownLexem(loc) => write!(f, "[{loc}] неизвестная лексема"),
+            Self::MalformedNumLit(_) => "H0101",
+            Self::UnknownLexem(_) => "H0102",
+            Self::UnterminatedString(_) => "H0103",
+            Self::InvalidEscape(_) => "H0104",
         }
     }
 }
 
+impl ToDiagnostic for LexError {
+    fn diagnostic(&self) -> Diagnostic {
+        let diagnostic = match self {
+            Self::MalformedNumLit(loc) => Diagnostic::new(tr!(
+                "неправильный float литерал",
+                "malformed number literal"
+            ))
+            .at(loc),
+            Self::UnknownLexem(loc) => {
+                Diagnostic::new(tr!("неизвестная лексема", "unknown lexeme")).at(loc)
+            }
+            Self::UnterminatedString(loc) => {
+                Diagnostic::new(tr!("незакрытая строка", "unterminated string"))
+                    .at(loc)
+                    .with_label(tr!("строка начинается здесь", "string starts here"))
+            }
+            Self::InvalidEscape(loc) => Diagnostic::new(tr!(
+                "неверная escape-последовательность в строке",
+                "invalid escape sequence in string"
+            ))
+            .at(loc)
+            .with_note(tr!(
+                "допустимы \\n, \\t, \\r, \\0, \\\", \\\\ и \\u{{XXXX}}",
+                "valid escapes are \\n, \\t, \\r, \\0, \\\", \\\\ and \\u{{XXXX}}"
+            )),
+        };
+        diagnostic.with_code(self.code())
+    }
+}
+
+impl fmt::Display for LexError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.diagnostic())
+    }
+}
+
+#[derive(Clone, Debug)]
 pub enum ParseError {
     UnexpectedEof(Loc),
     UnmatchingBrace(Loc),
     UnexpectedToken(Loc),
     ExpectedSemi(Loc),
     ExpectedParen(Loc),
+    ExpectedBrace(Loc),
     ExpectedIdent(Loc),
     ExpectedAssign(Loc),
+    ExpectedIn(Loc),
+    ExpectedRange(Loc),
     UnknownVariable(Loc),
+    OutOfScopeVariable(Loc),
+    UnknownFunction(Loc),
+    NestedFunction(Loc),
+    DuplicateFunction(Loc),
+    DuplicateParam(Loc),
 }
 
-impl fmt::Display for ParseError {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+impl ParseError {
+    pub fn code(&self) -> &'static str {
         match self {
-            Self::UnexpectedEof(loc) => write!(f, "[{loc}] неожиданный конец файла"),
-            Self::UnmatchingBrace(loc) => {
-                write!(f, "[{loc}] неверная скобочная последовательность")
-            }
-            Self::UnexpectedToken(loc) => write!(f, "[{loc}] неожиданный токен"),
-            Self::ExpectedSemi(loc) => write!(f, "[{loc}] ожидалась точка с запятой"),
-            Self::ExpectedParen(loc) => write!(f, "[{loc}] ожидалась скобка"),
-            Self::ExpectedIdent(loc) => write!(f, "[{loc}] ожидался идентификатор"),
-            Self::ExpectedAssign(loc) => write!(f, "[{loc}] ожидался знак присвоения ="),
-            Self::UnknownVariable(loc) => write!(f, "[{loc}] неизвестная переменная"),
+            Self::UnexpectedEof(_) => "H0201",
+            Self::UnmatchingBrace(_) => "H0202",
+            Self::UnexpectedToken(_) => "H0203",
+            Self::ExpectedSemi(_) => "H0204",
+            Self::ExpectedParen(_) => "H0205",
+            Self::ExpectedBrace(_) => "H0206",
+            Self::ExpectedIdent(_) => "H0207",
+            Self::ExpectedAssign(_) => "H0208",
+            Self::ExpectedIn(_) => "H0209",
+            Self::ExpectedRange(_) => "H0210",
+            Self::UnknownVariable(_) => "H0211",
+            Self::OutOfScopeVariable(_) => "H0212",
+            Self::UnknownFunction(_) => "H0213",
+            Self::NestedFunction(_) => "H0214",
+            Self::DuplicateFunction(_) => "H0215",
+            Self::DuplicateParam(_) => "H0216",
         }
     }
 }
+
+impl ToDiagnostic for ParseError {
+    fn diagnostic(&self) -> Diagnostic {
+        let diagnostic = match self {
+            Self::UnexpectedEof(loc) => {
+                Diagnostic::new(tr!("неожиданный конец файла", "unexpected end of file")).at(loc)
+            }
+            Self::UnmatchingBrace(loc) => Diagnostic::new(tr!(
+                "неверная скобочная последовательность",
+                "unmatched parenthesis"
+            ))
+            .at(loc),
+            Self::UnexpectedToken(loc) => {
+                Diagnostic::new(tr!("неожиданный токен", "unexpected token")).at(loc)
+            }
+            Self::ExpectedSemi(loc) => {
+                Diagnostic::new(tr!("ожидалась точка с запятой", "expected semicolon"))
+                    .at(loc)
+                    .with_label(tr!("после этого", "after this"))
+            }
+            Self::ExpectedParen(loc) => {
+                Diagnostic::new(tr!("ожидалась скобка", "expected parenthesis")).at(loc)
+            }
+            Self::ExpectedBrace(loc) => {
+                Diagnostic::new(tr!("ожидалась фигурная скобка", "expected brace")).at(loc)
+            }
+            Self::ExpectedIdent(loc) => {
+                Diagnostic::new(tr!("ожидался идентификатор", "expected identifier")).at(loc)
+            }
+            Self::ExpectedAssign(loc) => Diagnostic::new(tr!(
+                "ожидался знак присвоения =",
+                "expected assignment sign ="
+            ))
+            .at(loc),
+            Self::ExpectedIn(loc) => {
+                Diagnostic::new(tr!("ожидалось ключевое слово in", "expected keyword in")).at(loc)
+            }
+            Self::ExpectedRange(loc) => Diagnostic::new(tr!(
+                "ожидался диапазон вида начало..конец",
+                "expected range of the form start..end"
+            ))
+            .at(loc),
+            Self::UnknownVariable(loc) => {
+                Diagnostic::new(tr!("неизвестная переменная", "unknown variable")).at(loc)
+            }
+            Self::OutOfScopeVariable(loc) => Diagnostic::new(tr!(
+                "переменная используется вне своей области видимости",
+                "variable is used outside of its scope"
+            ))
+            .at(loc)
+            .with_note(tr!(
+                "переменная, объявленная в блоке, недоступна после его конца",
+                "a variable declared in a block is not available after the block ends"
+            )),
+            Self::UnknownFunction(loc) => {
+                Diagnostic::new(tr!("неизвестная функция", "unknown function")).at(loc)
+            }
+            Self::NestedFunction(loc) => Diagnostic::new(tr!(
+                "функцию можно объявить только на верхнем уровне программы",
+                "functions can only be declared at the top level of the program"
+            ))
+            .at(loc),
+            Self::DuplicateFunction(loc) => {
+                Diagnostic::new(tr!("функция уже объявлена", "function is already declared"))
+                    .at(loc)
+            }
+            Self::DuplicateParam(loc) => Diagnostic::new(tr!(
+                "повторяющееся имя параметра",
+                "duplicate parameter name"
+            ))
+            .at(loc),
+        };
+        diagnostic.with_code(self.code())
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.diagnostic())
+    }
+}
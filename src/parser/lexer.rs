@@ -13,6 +13,7 @@ pub struct Lexer {
     line: usize,
     col: usize,
     in_comment: bool,
+    errors: Vec<LexError>,
 }
 
 impl Lexer {
@@ -24,13 +25,15 @@ impl Lexer {
             line: 0,
             col: 0,
             in_comment: false,
+            errors: vec![],
         }
     }
 
-    pub fn lex(&mut self) -> Result<(), LexError> {
+    // Ошибочные лексемы пропускаются, и разбор продолжается, так что
+    // за один проход находятся все ошибки
+    pub fn lex(&mut self) -> Result<(), Vec<LexError>> {
         let source = self.source.clone();
         let mut source_iter = source.chars().peekable();
-        let mut buf;
         while let Some(c) = source_iter.next() {
             if self.in_comment {
                 match c {
@@ -38,86 +41,141 @@ impl Lexer {
                         self.in_comment = false;
                         self.line += 1;
                         self.col = 0;
-                        continue;
-                    }
-                    _ => {
-                        self.col += 1;
-                        continue;
                     }
+                    _ => self.col += 1,
                 }
+                continue;
             }
-            match c {
-                '\n' => {
-                    self.line += 1;
-                    self.col = 0;
-                }
-                c if c.is_whitespace() => self.col += 1,
-                c if c.is_alphabetic() => {
-                    buf = Self::collect_token(c, &mut source_iter, |c| c.is_alphanumeric());
-                    let len = buf.len();
-                    self.parse_ident(buf)?;
-                    self.col += len;
-                }
-                c if c.is_ascii_digit() => {
-                    buf = Self::collect_token(c, &mut source_iter, |c| {
-                        c.is_ascii_digit() || c == '.' || c == '-'
-                    });
-                    let len = buf.len();
-                    self.parse_numlit(buf)?;
-                    self.col += len;
-                }
-                '=' => {
-                    self.push_token(TokenType::Assign);
-                    self.col += 1;
-                }
-                '+' => {
-                    self.push_token(TokenType::OpPlus);
-                    self.col += 1;
-                }
-                '-' => {
-                    self.push_token(TokenType::OpMinus);
-                    self.col += 1;
-                }
-                '*' => {
-                    self.push_token(TokenType::OpStar);
-                    self.col += 1;
+            if let Err(e) = self.lex_char(c, &mut source_iter) {
+                self.errors.push(e);
+            }
+        }
+        self.push_token(TokenType::Eof);
+        if self.errors.is_empty() {
+            Ok(())
+        } else {
+            Err(std::mem::take(&mut self.errors))
+        }
+    }
+
+    fn lex_char(&mut self, c: char, source_iter: &mut Peekable<Chars<'_>>) -> Result<(), LexError> {
+        match c {
+            '\n' => {
+                self.line += 1;
+                self.col = 0;
+            }
+            c if c.is_whitespace() => self.col += 1,
+            c if c.is_alphabetic() || c == '_' => {
+                let buf = Self::collect_token(c, source_iter, |c| c.is_alphanumeric() || c == '_');
+                let len = buf.chars().count();
+                self.parse_ident(buf)?;
+                self.col += len;
+            }
+            c if c.is_ascii_digit() => {
+                let mut buf = Self::collect_token(c, source_iter, |c| c.is_ascii_digit());
+                // Точка входит в литерал, только если за ней идет цифра,
+                // иначе это может быть оператор диапазона 0..10
+                let mut lookahead = source_iter.clone();
+                if lookahead.next() == Some('.')
+                    && lookahead.peek().is_some_and(|c| c.is_ascii_digit())
+                {
+                    source_iter.next();
+                    buf.push('.');
+                    buf.extend(iter::from_fn(|| {
+                        source_iter.next_if(|c| c.is_ascii_digit())
+                    }));
                 }
-                '/' => {
-                    if let Some(next_c) = source_iter.peek() {
-                        if *next_c == '/' {
-                            self.in_comment = true;
-                            self.col += 2;
-                            continue;
-                        }
+                let len = buf.len();
+                self.parse_numlit(buf)?;
+                self.col += len;
+            }
+            '=' => {
+                self.push_operator(source_iter, '=', TokenType::OpEq, Some(TokenType::Assign))?
+            }
+            '!' => {
+                self.push_operator(source_iter, '=', TokenType::OpNeq, Some(TokenType::OpNot))?
+            }
+            '<' => self.push_operator(
+                source_iter,
+                '=',
+                TokenType::OpLessEq,
+                Some(TokenType::OpLess),
+            )?,
+            '>' => self.push_operator(
+                source_iter,
+                '=',
+                TokenType::OpGreaterEq,
+                Some(TokenType::OpGreater),
+            )?,
+            '&' => self.push_operator(source_iter, '&', TokenType::OpAnd, None)?,
+            '|' => self.push_operator(source_iter, '|', TokenType::OpOr, None)?,
+            '+' => {
+                self.push_token(TokenType::OpPlus);
+                self.col += 1;
+            }
+            '-' => {
+                self.push_token(TokenType::OpMinus);
+                self.col += 1;
+            }
+            '*' => {
+                self.push_token(TokenType::OpStar);
+                self.col += 1;
+            }
+            '/' => {
+                if let Some(next_c) = source_iter.peek() {
+                    if *next_c == '/' {
+                        self.in_comment = true;
+                        self.col += 2;
+                        return Ok(());
                     }
-                    self.push_token(TokenType::OpSlash);
-                    self.col += 1;
-                }
-                '(' => {
-                    self.push_token(TokenType::ParenLeft);
-                    self.col += 1;
                 }
-                ')' => {
-                    self.push_token(TokenType::ParenRight);
-                    self.col += 1;
-                }
-                ';' => {
-                    self.push_token(TokenType::Semicolon);
-                    self.col += 1;
-                }
-                _ => {
-                    return Err(LexError::UnknownLexem(Loc::new(
-                        self.file.clone(),
-                        self.line,
-                        self.col,
-                    )))
+                self.push_token(TokenType::OpSlash);
+                self.col += 1;
+            }
+            '(' => {
+                self.push_token(TokenType::ParenLeft);
+                self.col += 1;
+            }
+            ')' => {
+                self.push_token(TokenType::ParenRight);
+                self.col += 1;
+            }
+            '"' => self.lex_string(source_iter)?,
+            '.' => {
+                if source_iter.next_if_eq(&'.').is_none() {
+                    return Err(self.unknown_lexem());
                 }
+                self.push_wide_token(TokenType::DotDot, 2);
+                self.col += 2;
+            }
+            '{' => {
+                self.push_token(TokenType::BraceLeft);
+                self.col += 1;
+            }
+            '}' => {
+                self.push_token(TokenType::BraceRight);
+                self.col += 1;
             }
+            ',' => {
+                self.push_token(TokenType::Comma);
+                self.col += 1;
+            }
+            ';' => {
+                self.push_token(TokenType::Semicolon);
+                self.col += 1;
+            }
+            _ => return Err(self.unknown_lexem()),
         }
-        self.push_token(TokenType::Eof);
         Ok(())
     }
 
+    // Неизвестный символ пропускается
+    fn unknown_lexem(&mut self) -> LexError {
+        let loc = Loc::new(self.file.clone(), self.line, self.col);
+        self.col += 1;
+        LexError::UnknownLexem(loc)
+    }
+
     fn collect_token(
         c: char,
         iterator: &mut Peekable<Chars<'_>>,
@@ -129,19 +187,115 @@ impl Lexer {
     }
 
     fn parse_ident(&mut self, buf: String) -> Result<(), LexError> {
-        let loc = Loc::new(self.file.clone(), self.line, self.col);
+        let loc = Loc::new(self.file.clone(), self.line, self.col).with_len(buf.chars().count());
         let token = match buf.as_str() {
             "abs" => Ok(Token::new(TokenType::Builtin(BIn::Abs), loc)),
             "println" => Ok(Token::new(TokenType::Builtin(BIn::Println), loc)),
             "let" => Ok(Token::new(TokenType::Keyword(Kw::Let), loc)),
+            "true" => Ok(Token::new(TokenType::BoolLit(true), loc)),
+            "false" => Ok(Token::new(TokenType::BoolLit(false), loc)),
+            "nil" => Ok(Token::new(TokenType::Nil, loc)),
+            "len" => Ok(Token::new(TokenType::Builtin(BIn::Len), loc)),
+            "substr" => Ok(Token::new(TokenType::Builtin(BIn::Substr), loc)),
+            "upper" => Ok(Token::new(TokenType::Builtin(BIn::Upper), loc)),
+            "lower" => Ok(Token::new(TokenType::Builtin(BIn::Lower), loc)),
+            "to_string" => Ok(Token::new(TokenType::Builtin(BIn::ToString), loc)),
+            "parse_number" => Ok(Token::new(TokenType::Builtin(BIn::ParseNumber), loc)),
+            "readln" => Ok(Token::new(TokenType::Builtin(BIn::Readln), loc)),
+            "exit" => Ok(Token::new(TokenType::Builtin(BIn::Exit), loc)),
+            "if" => Ok(Token::new(TokenType::Keyword(Kw::If), loc)),
+            "else" => Ok(Token::new(TokenType::Keyword(Kw::Else), loc)),
+            "while" => Ok(Token::new(TokenType::Keyword(Kw::While), loc)),
+            "for" => Ok(Token::new(TokenType::Keyword(Kw::For), loc)),
+            "in" => Ok(Token::new(TokenType::Keyword(Kw::In), loc)),
+            "break" => Ok(Token::new(TokenType::Keyword(Kw::Break), loc)),
+            "continue" => Ok(Token::new(TokenType::Keyword(Kw::Continue), loc)),
+            "fn" => Ok(Token::new(TokenType::Keyword(Kw::Fn), loc)),
+            "return" => Ok(Token::new(TokenType::Keyword(Kw::Return), loc)),
             id => Ok(Token::new(TokenType::Ident(id.to_string()), loc)),
         }?;
         self.tokens.push(token);
         Ok(())
     }
 
+    // Открывающая кавычка уже поглощена
+    fn lex_string(&mut self, iterator: &mut Peekable<Chars<'_>>) -> Result<(), LexError> {
+        let mut loc = Loc::new(self.file.clone(), self.line, self.col);
+        self.col += 1;
+        let mut buf = String::new();
+        loop {
+            match iterator.next() {
+                None => return Err(LexError::UnterminatedString(loc)),
+                Some('"') => {
+                    let end = Loc::new(self.file.clone(), self.line, self.col);
+                    loc = loc.to(&end);
+                    self.col += 1;
+                    break;
+                }
+                Some('\n') => {
+                    self.line += 1;
+                    self.col = 0;
+                    buf.push('\n');
+                }
+                // После неверной escape-последовательности строка разбирается дальше
+                Some('\\') => match self.lex_escape(iterator) {
+                    Ok(c) => buf.push(c),
+                    Err(e @ LexError::InvalidEscape(_)) => self.errors.push(e),
+                    Err(_) => return Err(LexError::UnterminatedString(loc)),
+                },
+                Some(c) => {
+                    self.col += 1;
+                    buf.push(c);
+                }
+            }
+        }
+        self.tokens.push(Token::new(TokenType::StrLit(buf), loc));
+        Ok(())
+    }
+
+    // Обратная косая черта уже поглощена
+    fn lex_escape(&mut self, iterator: &mut Peekable<Chars<'_>>) -> Result<char, LexError> {
+        let loc = Loc::new(self.file.clone(), self.line, self.col).with_len(2);
+        if iterator.peek().is_none() {
+            return Err(LexError::UnterminatedString(loc));
+        }
+        let (c, consumed) = Self::decode_escape(iterator);
+        self.col += 1 + consumed;
+        c.ok_or(LexError::InvalidEscape(loc))
+    }
+
+    // Символ escape-последовательности (обратная косая черта уже поглощена)
+    // и число поглощенных символов. None - неверная последовательность.
+    // По этим же правилам ассемблер разбирает строковые константы
+    pub fn decode_escape(chars: &mut Peekable<Chars<'_>>) -> (Option<char>, usize) {
+        let c = match chars.next() {
+            None => return (None, 0),
+            Some('n') => '\n',
+            Some('t') => '\t',
+            Some('r') => '\r',
+            Some('0') => '\0',
+            Some('"') => '"',
+            Some('\\') => '\\',
+            // \u{XXXX} - символ Unicode с заданным шестнадцатеричным кодом
+            Some('u') => {
+                if chars.next_if_eq(&'{').is_none() {
+                    return (None, 1);
+                }
+                let code: String =
+                    iter::from_fn(|| chars.next_if(|c| c.is_ascii_hexdigit())).collect();
+                if chars.next_if_eq(&'}').is_none() {
+                    return (None, code.len() + 2);
+                }
+                let c = u32::from_str_radix(&code, 16).ok().and_then(char::from_u32);
+                return (c, code.len() + 3);
+            }
+            Some(_) => return (None, 1),
+        };
+        (Some(c), 1)
+    }
+
     fn parse_numlit(&mut self, buf: String) -> Result<(), LexError> {
-        let loc = Loc::new(self.file.clone(), self.line, self.col);
+        let loc = Loc::new(self.file.clone(), self.line, self.col).with_len(buf.len());
         let value = buf
             .parse()
             .map_err(|_| LexError::MalformedNumLit(loc.clone()))?;
@@ -150,12 +304,53 @@ impl Lexer {
         Ok(())
     }
 
+    // Оператор из двух символов, если следующий символ равен next,
+    // иначе оператор из одного символа (если такой есть)
+    fn push_operator(
+        &mut self,
+        iterator: &mut Peekable<Chars<'_>>,
+        next: char,
+        double: TokenType,
+        single: Option<TokenType>,
+    ) -> Result<(), LexError> {
+        if iterator.next_if_eq(&next).is_some() {
+            self.push_wide_token(double, 2);
+            self.col += 2;
+            return Ok(());
+        }
+        match single {
+            Some(ttype) => {
+                self.push_token(ttype);
+                self.col += 1;
+                Ok(())
+            }
+            None => Err(self.unknown_lexem()),
+        }
+    }
+
     fn push_token(&mut self, ttype: TokenType) {
-        let token = Token::new(ttype, Loc::new(self.file.clone(), self.line, self.col));
-        self.tokens.push(token);
+        self.push_wide_token(ttype, 1);
+    }
+
+    // Токен из len символов, начинающийся с текущей позиции
+    fn push_wide_token(&mut self, ttype: TokenType, len: usize) {
+        let loc = Loc::new(self.file.clone(), self.line, self.col).with_len(len);
+        self.tokens.push(Token::new(ttype, loc));
     }
 
     pub fn tokens(&self) -> &Vec<Token> {
         &self.tokens
     }
+
+    // Разбирается ли name как идентификатор, а не как ключевое слово
+    // или имя встроенной функции
+    pub fn is_identifier(name: &str) -> bool {
+        let mut lexer = Self::new(String::new(), name.to_string());
+        lexer.lex().is_ok()
+            && matches!(
+                lexer.tokens().as_slice(),
+                [Token { ttype: TokenType::Ident(id), .. }, Token { ttype: TokenType::Eof, .. }]
+                    if id == name
+            )
+    }
 }
@@ -5,14 +5,30 @@ pub enum TokenType {
     Ident(String),
     Builtin(BIn),
     NumLit(f64),
+    StrLit(String),
+    BoolLit(bool),
+    Nil,
     Keyword(Kw),
     Assign,
     OpPlus,
     OpMinus,
     OpStar,
     OpSlash,
+    OpEq,
+    OpNeq,
+    OpLess,
+    OpGreater,
+    OpLessEq,
+    OpGreaterEq,
+    OpAnd,
+    OpOr,
+    OpNot,
     ParenLeft,
     ParenRight,
+    BraceLeft,
+    BraceRight,
+    DotDot,
+    Comma,
     Semicolon,
     Eof,
 }
@@ -21,30 +37,162 @@ pub enum TokenType {
 pub enum BIn {
     Println,
     Abs,
+    Len,
+    Substr,
+    Upper,
+    Lower,
+    ToString,
+    ParseNumber,
+    Readln,
+    Exit,
+}
+
+impl BIn {
+    // Номер встроенной функции, используемый командой BIN
+    pub fn number(&self) -> u16 {
+        match self {
+            Self::Println => 0x0000,
+            Self::Abs => 0x0001,
+            Self::Len => 0x0002,
+            Self::Substr => 0x0003,
+            Self::Upper => 0x0004,
+            Self::Lower => 0x0005,
+            Self::ToString => 0x0006,
+            Self::ParseNumber => 0x0007,
+            Self::Readln => 0x0008,
+            Self::Exit => 0x0009,
+        }
+    }
+
+    pub fn from_number(number: u16) -> Option<Self> {
+        match number {
+            0x0000 => Some(Self::Println),
+            0x0001 => Some(Self::Abs),
+            0x0002 => Some(Self::Len),
+            0x0003 => Some(Self::Substr),
+            0x0004 => Some(Self::Upper),
+            0x0005 => Some(Self::Lower),
+            0x0006 => Some(Self::ToString),
+            0x0007 => Some(Self::ParseNumber),
+            0x0008 => Some(Self::Readln),
+            0x0009 => Some(Self::Exit),
+            _ => None,
+        }
+    }
+
+    pub fn arity(&self) -> usize {
+        match self {
+            Self::Substr => 3,
+            Self::Readln => 0,
+            _ => 1,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Println => "println",
+            Self::Abs => "abs",
+            Self::Len => "len",
+            Self::Substr => "substr",
+            Self::Upper => "upper",
+            Self::Lower => "lower",
+            Self::ToString => "to_string",
+            Self::ParseNumber => "parse_number",
+            Self::Readln => "readln",
+            Self::Exit => "exit",
+        }
+    }
 }
 
 #[derive(PartialEq, Clone, Debug)]
 pub enum Kw {
     Let,
+    If,
+    Else,
+    While,
+    For,
+    In,
+    Break,
+    Continue,
+    Fn,
+    Return,
 }
 
 use TokenType::*;
 
-#[derive(Clone, Debug)]
+// Участок исходного кода от (line, col) до (end_line, end_col), не включая конец
+#[derive(PartialEq, Clone, Debug)]
 pub struct Loc {
     file: String,
     line: usize,
     col: usize,
+    end_line: usize,
+    end_col: usize,
 }
 
 impl Loc {
+    // Участок длиной в один символ
     pub fn new(file: String, line: usize, col: usize) -> Self {
         Self {
             file,
             line: line + 1,
             col: col + 1,
+            end_line: line + 1,
+            end_col: col + 2,
+        }
+    }
+
+    // Номера строк и столбцов, начиная с 1, как их возвращают line() и col()
+    pub fn from_parts(
+        file: String,
+        (line, col): (usize, usize),
+        (end_line, end_col): (usize, usize),
+    ) -> Self {
+        Self {
+            file,
+            line,
+            col,
+            end_line,
+            end_col,
+        }
+    }
+
+    // Участок из len символов на одной строке
+    pub fn with_len(mut self, len: usize) -> Self {
+        self.end_line = self.line;
+        self.end_col = self.col + len.max(1);
+        self
+    }
+
+    // Участок от начала self до конца other
+    pub fn to(&self, other: &Loc) -> Loc {
+        Loc {
+            end_line: other.end_line,
+            end_col: other.end_col,
+            ..self.clone()
         }
     }
+
+    pub fn file(&self) -> &str {
+        &self.file
+    }
+
+    // Номера строки и столбца, начиная с 1
+    pub fn line(&self) -> usize {
+        self.line
+    }
+
+    pub fn col(&self) -> usize {
+        self.col
+    }
+
+    pub fn end_line(&self) -> usize {
+        self.end_line
+    }
+
+    pub fn end_col(&self) -> usize {
+        self.end_col
+    }
 }
 
 impl fmt::Display for Loc {
@@ -75,6 +223,9 @@ impl fmt::Display for Token {
         match ttype {
             Ident(id) => write!(f, "[{loc}] {id}"),
             NumLit(lit) => write!(f, "[{loc}] {lit}"),
+            StrLit(lit) => write!(f, "[{loc}] {lit:?}"),
+            BoolLit(lit) => write!(f, "[{loc}] {lit}"),
+            Nil => write!(f, "[{loc}] nil"),
             Builtin(b) => write!(f, "[{loc}] {b}"),
             Keyword(kw) => write!(f, "[{loc}] {kw}"),
             Assign => write!(f, "[{loc}] ="),
@@ -82,8 +233,21 @@ impl fmt::Display for Token {
             OpMinus => write!(f, "[{loc}] -"),
             OpStar => write!(f, "[{loc}] *"),
             OpSlash => write!(f, "[{loc}] /"),
+            OpEq => write!(f, "[{loc}] =="),
+            OpNeq => write!(f, "[{loc}] !="),
+            OpLess => write!(f, "[{loc}] <"),
+            OpGreater => write!(f, "[{loc}] >"),
+            OpLessEq => write!(f, "[{loc}] <="),
+            OpGreaterEq => write!(f, "[{loc}] >="),
+            OpAnd => write!(f, "[{loc}] &&"),
+            OpOr => write!(f, "[{loc}] ||"),
+            OpNot => write!(f, "[{loc}] !"),
             ParenLeft => write!(f, "[{loc}] ("),
             ParenRight => write!(f, "[{loc}] )"),
+            BraceLeft => write!(f, "[{loc}] {{"),
+            BraceRight => write!(f, "[{loc}] }}"),
+            DotDot => write!(f, "[{loc}] .."),
+            Comma => write!(f, "[{loc}] ,"),
             Semicolon => write!(f, "[{loc}] ;"),
             Eof => write!(f, "[{loc}]"),
         }
@@ -92,10 +256,7 @@ impl fmt::Display for Token {
 
 impl fmt::Display for BIn {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        match self {
-            Self::Abs => write!(f, "builtin abs"),
-            Self::Println => write!(f, "builtin println"),
-        }
+        write!(f, "builtin {}", self.name())
     }
 }
 
@@ -103,6 +264,15 @@ impl fmt::Display for Kw {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
             Self::Let => write!(f, "keyword let"),
+            Self::If => write!(f, "keyword if"),
+            Self::Else => write!(f, "keyword else"),
+            Self::While => write!(f, "keyword while"),
+            Self::For => write!(f, "keyword for"),
+            Self::In => write!(f, "keyword in"),
+            Self::Break => write!(f, "keyword break"),
+            Self::Continue => write!(f, "keyword continue"),
+            Self::Fn => write!(f, "keyword fn"),
+            Self::Return => write!(f, "keyword return"),
         }
     }
 }
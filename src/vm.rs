@@ -1,3 +1,7 @@
+pub mod debug;
+pub mod disasm;
 pub mod errors;
+pub mod host;
 pub mod instruction;
+pub mod verifier;
 pub mod vm;
@@ -0,0 +1,150 @@
+use crate::parser::tokens::Loc;
+use crate::vm::errors::*;
+use crate::vm::vm::ByteReader;
+
+// Отладочная информация: каким местам исходного кода соответствуют
+// команды и по каким адресам начинаются функции.
+// Формат секции описан в docs/bytecode.txt
+// Строка и столбец начала, строка и столбец конца
+type Span = [u32; 4];
+
+#[derive(Clone, Default)]
+pub struct DebugInfo {
+    file: String,
+    // Смещение команды и участок кода. Запись действует до следующей,
+    // смещения возрастают
+    lines: Vec<(u32, Span)>,
+    // Адрес начала и имя функции
+    functions: Vec<(u32, String)>,
+}
+
+impl DebugInfo {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Команды, начиная со смещения offset, относятся к месту loc
+    pub fn add_line(&mut self, offset: usize, loc: &Loc) {
+        self.file = loc.file().to_string();
+        let span = [loc.line(), loc.col(), loc.end_line(), loc.end_col()].map(|n| n as u32);
+        self.push_line(offset, span);
+    }
+
+    fn push_line(&mut self, offset: usize, span: Span) {
+        let entry = (offset as u32, span);
+        match self.lines.last_mut() {
+            Some(last) if last.0 == entry.0 => *last = entry,
+            Some(last) if last.1 == entry.1 => {}
+            _ => self.lines.push(entry),
+        }
+    }
+
+    pub fn add_function(&mut self, address: usize, name: &str) {
+        self.functions.push((address as u32, name.to_string()));
+    }
+
+    // Переносит информацию на измененный код: offset переводит старое смещение
+    // команды в новое, function - старый адрес функции в новый
+    // (None, если функция удалена)
+    pub fn relocate(
+        &self,
+        offset: impl Fn(usize) -> usize,
+        function: impl Fn(usize) -> Option<usize>,
+    ) -> Self {
+        let mut debug = Self {
+            file: self.file.clone(),
+            lines: vec![],
+            functions: vec![],
+        };
+        for (old, span) in &self.lines {
+            debug.push_line(offset(*old as usize), *span);
+        }
+        for (address, name) in &self.functions {
+            if let Some(address) = function(*address as usize) {
+                debug.add_function(address, name);
+            }
+        }
+        debug
+    }
+
+    pub fn loc(&self, offset: usize) -> Option<Loc> {
+        let next = self.lines.partition_point(|(o, _)| *o as usize <= offset);
+        let (_, span) = self.lines.get(next.checked_sub(1)?)?;
+        let [line, col, end_line, end_col] = span.map(|n| n as usize);
+        Some(Loc::from_parts(
+            self.file.clone(),
+            (line, col),
+            (end_line, end_col),
+        ))
+    }
+
+    pub fn function_addresses(&self) -> impl Iterator<Item = usize> + '_ {
+        self.functions.iter().map(|(address, _)| *address as usize)
+    }
+
+    pub fn function(&self, address: usize) -> Option<&str> {
+        self.functions
+            .iter()
+            .find(|(a, _)| *a as usize == address)
+            .map(|(_, name)| name.as_str())
+    }
+
+    pub fn to_bytes(&self) -> Result<Vec<u8>, BytecodeError> {
+        let mut bytes = vec![];
+        Self::write_str(&mut bytes, &self.file)?;
+        bytes.extend_from_slice(&u32::to_le_bytes(self.lines.len() as u32));
+        for (offset, span) in &self.lines {
+            bytes.extend_from_slice(&u32::to_le_bytes(*offset));
+            for n in span {
+                bytes.extend_from_slice(&u32::to_le_bytes(*n));
+            }
+        }
+        bytes.extend_from_slice(&u32::to_le_bytes(self.functions.len() as u32));
+        for (address, name) in &self.functions {
+            bytes.extend_from_slice(&u32::to_le_bytes(*address));
+            Self::write_str(&mut bytes, name)?;
+        }
+        Ok(bytes)
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, BytecodeError> {
+        let mut reader = ByteReader::new(bytes);
+        let file = Self::read_str(&mut reader)?;
+        let mut lines = vec![];
+        for _ in 0..reader.u32()? {
+            let offset = reader.u32()?;
+            let span = [reader.u32()?, reader.u32()?, reader.u32()?, reader.u32()?];
+            // Строки и столбцы нумеруются с 1, конец участка не раньше начала
+            let [line, col, end_line, end_col] = span;
+            if line == 0 || col == 0 || (end_line, end_col) < (line, col) {
+                return Err(BytecodeError::IncorrectRep);
+            }
+            lines.push((offset, span));
+        }
+        let mut functions = vec![];
+        for _ in 0..reader.u32()? {
+            functions.push((reader.u32()?, Self::read_str(&mut reader)?));
+        }
+        if !reader.is_empty() {
+            return Err(BytecodeError::IncorrectRep);
+        }
+        Ok(Self {
+            file,
+            lines,
+            functions,
+        })
+    }
+
+    fn write_str(bytes: &mut Vec<u8>, s: &str) -> Result<(), BytecodeError> {
+        let len = u16::try_from(s.len()).map_err(|_| BytecodeError::NameTooLong(s.len()))?;
+        bytes.extend_from_slice(&u16::to_le_bytes(len));
+        bytes.extend_from_slice(s.as_bytes());
+        Ok(())
+    }
+
+    fn read_str(reader: &mut ByteReader) -> Result<String, BytecodeError> {
+        let len = reader.u16()? as usize;
+        let s = std::str::from_utf8(reader.take(len)?).map_err(|_| BytecodeError::IncorrectRep)?;
+        Ok(s.to_string())
+    }
+}
@@ -0,0 +1,124 @@
+use crate::locale::tr;
+use crate::parser::tokens::BIn;
+use crate::vm::errors::*;
+use crate::vm::instruction::*;
+use crate::vm::vm::{Bytecode, Value, VERSION};
+use std::fmt::Write;
+
+pub struct Disassembler {
+    program: Vec<u8>,
+    consts: Vec<Value>,
+}
+
+impl Disassembler {
+    pub fn new(bytecode: Vec<u8>) -> Result<Self, BytecodeError> {
+        let Bytecode { code, consts, .. } = Bytecode::from_bytes(&bytecode)?;
+        Ok(Self {
+            program: code,
+            consts,
+        })
+    }
+
+    pub fn disassemble(&self) -> Result<String, BytecodeError> {
+        let mut out = String::new();
+        // Запись в String не может завершиться ошибкой
+        let _ = writeln!(
+            out,
+            "{}",
+            tr!("Версия формата: {VERSION}", "Format version: {VERSION}")
+        );
+        let size = self.program.len();
+        let _ = writeln!(out, "{}", tr!("Код ({size} байт):", "Code ({size} bytes):"));
+        let mut offset = 0;
+        while offset < self.program.len() {
+            let byte = self.program[offset];
+            let inst = Instruction::try_from(byte)
+                .map_err(|_| BytecodeError::UnknownOpcode(offset, byte))?;
+            let size = inst.operands_size();
+            let operands = self
+                .program
+                .get((offset + 1)..(offset + 1 + size))
+                .ok_or(BytecodeError::TruncatedInstruction(offset))?;
+            let _ = writeln!(out, "{}", self.format_instruction(offset, inst, operands));
+            offset += 1 + size;
+        }
+        let count = self.consts.len();
+        let _ = writeln!(
+            out,
+            "{}",
+            tr!("Константы ({count}):", "Constants ({count}):")
+        );
+        for (i, c) in self.consts.iter().enumerate() {
+            let _ = writeln!(out, "  {i:>5}  {c:#}");
+        }
+        Ok(out)
+    }
+
+    fn format_instruction(&self, offset: usize, inst: Instruction, operands: &[u8]) -> String {
+        let bytes: Vec<String> = self.program[offset..(offset + 1 + operands.len())]
+            .iter()
+            .map(|b| format!("{b:02x}"))
+            .collect();
+        let bytes = bytes.join(" ");
+        let (operand, comment) = match inst {
+            Instruction::Push => {
+                let index = u16::from_le_bytes([operands[0], operands[1]]);
+                let comment = match self.consts.get(index as usize) {
+                    Some(c) => format!("{c:#}"),
+                    None => tr!("нет такой константы", "no such constant"),
+                };
+                (format!("{index}"), Some(comment))
+            }
+            Instruction::Jmp | Instruction::Jf => {
+                let jump = u16::from_le_bytes([operands[0], operands[1]]) as usize;
+                (
+                    format!("+{jump}"),
+                    Some(self.format_target(Some(offset + jump))),
+                )
+            }
+            Instruction::Jback => {
+                let jump = u16::from_le_bytes([operands[0], operands[1]]) as usize;
+                (
+                    format!("-{jump}"),
+                    Some(self.format_target(offset.checked_sub(jump))),
+                )
+            }
+            Instruction::Bin => {
+                let number = u16::from_le_bytes([operands[0], operands[1]]);
+                let comment = match BIn::from_number(number) {
+                    Some(bin) => String::from(bin.name()),
+                    None => tr!("неизвестная функция", "unknown function"),
+                };
+                (format!("{number:#06x}"), Some(comment))
+            }
+            Instruction::Call => {
+                let address =
+                    u32::from_le_bytes([operands[0], operands[1], operands[2], operands[3]]);
+                let target = self.format_target(Some(address as usize));
+                (format!("{address:#06x}"), Some(target))
+            }
+            // Имена функций приложения в байткоде не хранятся
+            Instruction::Hcall => {
+                let index = u16::from_le_bytes([operands[0], operands[1]]);
+                (format!("{index}"), None)
+            }
+            Instruction::Liv | Instruction::Lfv | Instruction::Lig | Instruction::Lfg => {
+                let idx = u32::from_le_bytes([operands[0], operands[1], operands[2], operands[3]]);
+                (format!("{idx}"), None)
+            }
+            _ => (String::new(), None),
+        };
+        let line = format!("  {offset:04x}  {bytes:<15}  {inst:<6} {operand}");
+        match comment {
+            Some(c) => format!("{line:<44} ; {c}"),
+            None => String::from(line.trim_end()),
+        }
+    }
+
+    fn format_target(&self, target: Option<usize>) -> String {
+        match target {
+            Some(t) if t < self.program.len() => format!("-> {t:04x}"),
+            _ => tr!("-> за пределами программы", "-> outside of the program"),
+        }
+    }
+}
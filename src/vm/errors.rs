@@ -1,5 +1,11 @@
+use crate::diagnostics::{Diagnostic, ToDiagnostic};
+use crate::locale::tr;
+use crate::parser::tokens::Loc;
+use crate::vm::vm::MAX_VARIABLES;
 use std::fmt;
+use std::io;
 
+#[derive(Debug)]
 pub enum InterpretationError {
     OpcodeError,
     UnexpectedEndError,
@@ -7,37 +13,300 @@ pub enum InterpretationError {
     EmptyStackError,
     ZeroDivisionError,
     UnknownBuiltin,
+    StackOverflow,
+    ReturnOutsideFunction,
+    // Операция и тип операнда
+    UnaryTypeError(&'static str, &'static str),
+    // Операция и типы левого и правого операндов
+    BinaryTypeError(&'static str, &'static str, &'static str),
+    // Функция и недопустимое значение аргумента
+    InvalidArgument(&'static str, f64),
+    OutputError(io::Error),
+    InputError(io::Error),
+    UnknownHostFunction(u16),
+    // Имя функции приложения и сообщение, которое она вернула
+    HostError(String, String),
+    InvalidExitCode(f64),
+    UnassignedVariable(usize),
+}
+
+impl InterpretationError {
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::OpcodeError => "H0401",
+            Self::UnexpectedEndError => "H0402",
+            Self::BadConstsIndexError => "H0403",
+            Self::EmptyStackError => "H0404",
+            Self::ZeroDivisionError => "H0405",
+            Self::UnknownBuiltin => "H0406",
+            Self::StackOverflow => "H0407",
+            Self::ReturnOutsideFunction => "H0408",
+            Self::UnaryTypeError(..) => "H0409",
+            Self::BinaryTypeError(..) => "H0410",
+            Self::InvalidArgument(..) => "H0411",
+            Self::OutputError(_) => "H0412",
+            Self::InputError(_) => "H0413",
+            Self::UnknownHostFunction(_) => "H0414",
+            Self::HostError(..) => "H0415",
+            Self::InvalidExitCode(_) => "H0416",
+            Self::UnassignedVariable(_) => "H0417",
+        }
+    }
 }
 
 impl fmt::Display for InterpretationError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        match self {
-            InterpretationError::OpcodeError => write!(f, "неизвестный код комманды"),
-            InterpretationError::UnexpectedEndError => write!(f, "неожиданный конец программы"),
-            InterpretationError::BadConstsIndexError => {
-                write!(f, "индекс таблицы констант вышел за границы")
-            }
-            InterpretationError::EmptyStackError => write!(f, "стек оказался пустым"),
-            InterpretationError::ZeroDivisionError => write!(f, "деление на 0"),
-            InterpretationError::UnknownBuiltin => write!(f, "неизвестная встроенная функция"),
+        let message = match self {
+            InterpretationError::OpcodeError => {
+                tr!("неизвестный код комманды", "unknown instruction opcode")
+            }
+            InterpretationError::UnexpectedEndError => {
+                tr!("неожиданный конец программы", "unexpected end of program")
+            }
+            InterpretationError::BadConstsIndexError => tr!(
+                "индекс таблицы констант вышел за границы",
+                "constant table index is out of bounds"
+            ),
+            InterpretationError::EmptyStackError => tr!("стек оказался пустым", "stack is empty"),
+            InterpretationError::ZeroDivisionError => tr!("деление на 0", "division by zero"),
+            InterpretationError::UnknownBuiltin => {
+                tr!("неизвестная встроенная функция", "unknown builtin function")
+            }
+            InterpretationError::StackOverflow => tr!(
+                "переполнение стека вызовов: слишком глубокая рекурсия",
+                "call stack overflow: recursion is too deep"
+            ),
+            InterpretationError::ReturnOutsideFunction => tr!(
+                "возврат из функции вне вызова функции",
+                "return outside of a function call"
+            ),
+            InterpretationError::UnaryTypeError(op, t) => tr!(
+                "операция {op} неприменима к значению типа {t}",
+                "operation {op} cannot be applied to a value of type {t}"
+            ),
+            InterpretationError::BinaryTypeError(op, left, right) => tr!(
+                "операция {op} неприменима к значениям типов {left} и {right}",
+                "operation {op} cannot be applied to values of types {left} and {right}"
+            ),
+            InterpretationError::OutputError(e) => tr!("ошибка вывода: {e}", "output error: {e}"),
+            InterpretationError::InputError(e) => tr!("ошибка ввода: {e}", "input error: {e}"),
+            InterpretationError::UnknownHostFunction(index) => tr!(
+                "неизвестная функция приложения с номером {index}",
+                "unknown host function with index {index}"
+            ),
+            InterpretationError::HostError(name, message) => format!("{name}: {message}"),
+            InterpretationError::InvalidArgument(func, n) => tr!(
+                "{func}: ожидалось неотрицательное целое число, получено {n}",
+                "{func}: expected a non-negative integer, got {n}"
+            ),
+            InterpretationError::InvalidExitCode(n) => tr!(
+                "exit: код завершения должен быть целым числом от 0 до 255, получено {n}",
+                "exit: exit code must be an integer from 0 to 255, got {n}"
+            ),
+            InterpretationError::UnassignedVariable(idx) => tr!(
+                "переменной {idx} еще не присвоено значение",
+                "variable {idx} has not been assigned yet"
+            ),
+        };
+        write!(f, "{message}")
+    }
+}
+
+// Функция (None - основная программа) и место в ней. Одинаковые вызовы
+// подряд (например, при рекурсии) записываются один раз
+#[derive(Debug)]
+pub struct TraceEntry {
+    pub function: Option<String>,
+    pub loc: Option<Loc>,
+    pub repeats: usize,
+}
+
+// Ошибка выполнения со стеком вызовов, начиная с места ошибки.
+// Места известны, только если в байткоде есть отладочная информация
+#[derive(Debug)]
+pub struct RuntimeError {
+    pub error: InterpretationError,
+    pub trace: Vec<TraceEntry>,
+}
+
+impl RuntimeError {
+    pub fn loc(&self) -> Option<&Loc> {
+        self.trace.first()?.loc.as_ref()
+    }
+}
+
+impl ToDiagnostic for RuntimeError {
+    fn diagnostic(&self) -> Diagnostic {
+        let mut trace = tr!("Стек вызовов:", "Call stack:");
+        for entry in &self.trace {
+            match &entry.function {
+                Some(name) => trace += &tr!("\n  в функции {name}", "\n  in function {name}"),
+                None => trace += &tr!("\n  в основной программе", "\n  in main program"),
+            }
+            if let Some(loc) = &entry.loc {
+                trace += &format!(" [{loc}]");
+            }
+            if entry.repeats > 1 {
+                let n = entry.repeats;
+                trace += &tr!(" (повторений: {n})", " (repeated {n} times)");
+            }
+        }
+        let diagnostic = Diagnostic::new(self.error.to_string())
+            .with_code(self.error.code())
+            .with_note(trace);
+        match self.loc() {
+            Some(loc) => diagnostic.at(loc),
+            None => diagnostic,
         }
     }
 }
 
+impl fmt::Display for RuntimeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.diagnostic())
+    }
+}
+
+#[derive(Debug)]
 pub enum BytecodeError {
     UnexpectedEof,
     IncorrectRep,
+    UnknownOpcode(usize, u8),
+    TruncatedInstruction(usize),
+    BadMagic,
+    UnsupportedVersion(u16),
+    UnknownSection(u8),
+    DuplicateSection(u8),
+    MissingSection(u8),
+    TrailingData,
+    BadJumpTarget(usize),
+    BadConstIndex(usize, u16),
+    UnknownBuiltin(usize, u16),
+    UnknownHostFunction(usize, u16),
+    StackMismatch(usize),
+    StackUnderflow(usize),
+    FallsOffEnd(usize),
+    // Длина имени файла или функции в байтах
+    NameTooLong(usize),
+    BadVariableIndex(usize, u32),
+    BadDebugFunction(usize),
+}
+
+impl BytecodeError {
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::UnexpectedEof => "H0501",
+            Self::IncorrectRep => "H0502",
+            Self::UnknownOpcode(..) => "H0503",
+            Self::TruncatedInstruction(_) => "H0504",
+            Self::BadMagic => "H0505",
+            Self::UnsupportedVersion(_) => "H0506",
+            Self::UnknownSection(_) => "H0507",
+            Self::DuplicateSection(_) => "H0508",
+            Self::MissingSection(_) => "H0509",
+            Self::TrailingData => "H0510",
+            Self::BadJumpTarget(_) => "H0511",
+            Self::BadConstIndex(..) => "H0512",
+            Self::UnknownBuiltin(..) => "H0513",
+            Self::UnknownHostFunction(..) => "H0514",
+            Self::StackMismatch(_) => "H0515",
+            Self::StackUnderflow(_) => "H0516",
+            Self::FallsOffEnd(_) => "H0517",
+            Self::NameTooLong(_) => "H0518",
+            Self::BadVariableIndex(..) => "H0519",
+            Self::BadDebugFunction(_) => "H0520",
+        }
+    }
+}
+
+impl ToDiagnostic for BytecodeError {
+    fn diagnostic(&self) -> Diagnostic {
+        Diagnostic::new(self.to_string()).with_code(self.code())
+    }
 }
 
 impl fmt::Display for BytecodeError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        match self {
-            BytecodeError::UnexpectedEof => {
-                write!(f, "неожиданный конец файла при чтении константы")
+        let message = match self {
+            BytecodeError::UnexpectedEof => tr!(
+                "неожиданный конец файла байткода",
+                "unexpected end of bytecode file"
+            ),
+            BytecodeError::IncorrectRep => tr!(
+                "данные были неверно представлены в двоичном виде",
+                "data has an incorrect binary representation"
+            ),
+            BytecodeError::UnknownOpcode(offset, byte) => tr!(
+                "[{offset:#06x}] неизвестный код команды {byte:#04x}",
+                "[{offset:#06x}] unknown opcode {byte:#04x}"
+            ),
+            BytecodeError::TruncatedInstruction(offset) => tr!(
+                "[{offset:#06x}] у команды не хватает операндов",
+                "[{offset:#06x}] instruction is missing operands"
+            ),
+            BytecodeError::BadMagic => tr!(
+                "файл не является файлом байткода Hammer",
+                "file is not a Hammer bytecode file"
+            ),
+            BytecodeError::UnsupportedVersion(v) => tr!(
+                "неподдерживаемая версия формата байткода: {v}",
+                "unsupported bytecode format version: {v}"
+            ),
+            BytecodeError::UnknownSection(s) => {
+                tr!("неизвестная секция {s:#04x}", "unknown section {s:#04x}")
             }
-            BytecodeError::IncorrectRep => {
-                write!(f, "данные были неверно представлены в двоичном виде")
+            BytecodeError::DuplicateSection(s) => tr!(
+                "секция {s:#04x} встречается дважды",
+                "section {s:#04x} appears twice"
+            ),
+            BytecodeError::MissingSection(s) => {
+                tr!("отсутствует секция {s:#04x}", "missing section {s:#04x}")
             }
-        }
+            BytecodeError::TrailingData => tr!(
+                "лишние данные после последней секции",
+                "trailing data after the last section"
+            ),
+            BytecodeError::BadJumpTarget(offset) => tr!(
+                "[{offset:#06x}] переход за пределы кода или внутрь команды",
+                "[{offset:#06x}] jump outside the code or into the middle of an instruction"
+            ),
+            BytecodeError::BadConstIndex(offset, index) => tr!(
+                "[{offset:#06x}] нет константы с индексом {index}",
+                "[{offset:#06x}] no constant with index {index}"
+            ),
+            BytecodeError::UnknownBuiltin(offset, number) => tr!(
+                "[{offset:#06x}] неизвестная встроенная функция {number:#06x}",
+                "[{offset:#06x}] unknown builtin function {number:#06x}"
+            ),
+            BytecodeError::UnknownHostFunction(offset, index) => tr!(
+                "[{offset:#06x}] неизвестная функция приложения {index}",
+                "[{offset:#06x}] unknown host function {index}"
+            ),
+            BytecodeError::StackMismatch(offset) => tr!(
+                "[{offset:#06x}] глубина стека на этой команде зависит от пути выполнения",
+                "[{offset:#06x}] stack depth at this instruction depends on the execution path"
+            ),
+            BytecodeError::StackUnderflow(offset) => tr!(
+                "[{offset:#06x}] команда снимает значение с пустого стека",
+                "[{offset:#06x}] instruction pops from an empty stack"
+            ),
+            BytecodeError::FallsOffEnd(offset) => tr!(
+                "[{offset:#06x}] выполнение доходит до конца кода без HLT",
+                "[{offset:#06x}] execution reaches the end of the code without HLT"
+            ),
+            BytecodeError::NameTooLong(len) => tr!(
+                "имя длиной {len} байт не помещается в отладочную информацию (не больше 65535 байт)",
+                "a name of {len} bytes does not fit into the debug info (at most 65535 bytes)"
+            ),
+            BytecodeError::BadVariableIndex(offset, index) => tr!(
+                "[{offset:#06x}] номер переменной {index} не меньше {MAX_VARIABLES}",
+                "[{offset:#06x}] variable index {index} is not less than {MAX_VARIABLES}"
+            ),
+            BytecodeError::BadDebugFunction(address) => tr!(
+                "[{address:#06x}] функция из отладочной информации начинается не с команды",
+                "[{address:#06x}] a function from the debug info does not start at an instruction"
+            ),
+        };
+        write!(f, "{message}")
     }
 }
@@ -0,0 +1,72 @@
+use crate::embed::errors::EmbedError;
+use crate::error::{ErrorKind, HammerError};
+use crate::parser::lexer::Lexer;
+use crate::vm::vm::Value;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+// Аргументы передаются в порядке их записи в вызове. Сообщение об ошибке
+// превращается в InterpretationError::HostError
+pub type HostFn = dyn Fn(&[Value]) -> Result<Value, String>;
+
+#[derive(Clone)]
+pub struct HostFunction {
+    pub name: String,
+    pub arity: usize,
+    pub func: Rc<HostFn>,
+}
+
+// Функции, которые приложение предоставляет программам на Hammer.
+// Компилятор и VM должны получить один и тот же набор: в байткоде
+// функция обозначается своим номером в порядке регистрации
+#[derive(Clone, Default)]
+pub struct HostFunctions {
+    functions: Vec<HostFunction>,
+    // Номер функции по имени
+    indices: HashMap<String, usize>,
+}
+
+impl HostFunctions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Повторная регистрация функции с тем же именем заменяет прежнюю.
+    // Имя должно быть идентификатором: ключевые слова и имена встроенных
+    // функций лексер разбирает иначе, и функция была бы недоступна
+    pub fn register(
+        &mut self,
+        name: &str,
+        arity: usize,
+        func: impl Fn(&[Value]) -> Result<Value, String> + 'static,
+    ) -> Result<&mut Self, HammerError> {
+        if !Lexer::is_identifier(name) {
+            return Err(ErrorKind::Embed(EmbedError::InvalidHostName(name.to_string())).into());
+        }
+        let function = HostFunction {
+            name: name.to_string(),
+            arity,
+            func: Rc::new(func),
+        };
+        match self.indices.get(name) {
+            Some(&index) => self.functions[index] = function,
+            // В байткоде номер функции занимает 2 байта
+            None if self.functions.len() > u16::MAX as usize => {
+                return Err(ErrorKind::Embed(EmbedError::TooManyHostFunctions).into())
+            }
+            None => {
+                self.indices.insert(name.to_string(), self.functions.len());
+                self.functions.push(function);
+            }
+        }
+        Ok(self)
+    }
+
+    pub fn get(&self, index: usize) -> Option<&HostFunction> {
+        self.functions.get(index)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &HostFunction> {
+        self.functions.iter()
+    }
+}
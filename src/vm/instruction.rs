@@ -1,28 +1,88 @@
 use crate::vm::errors::*;
+use std::fmt;
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
 pub enum Instruction {
-    Nop,
-    Push,
-    Add,
-    Sub,
-    Mul,
-    Div,
-    Neg,
-    Eq,
-    Neq,
-    Gr,
-    Ls,
-    Ge,
-    Le,
-    Jmp,
-    Jf,
-    Jback,
-    Bin,
-    Liv,
-    Lfv,
-    Dbg,
-    Hlt,
+    Nop = 0x00,
+    Push = 0x01,
+    Add = 0x02,
+    Sub = 0x03,
+    Mul = 0x04,
+    Div = 0x05,
+    Neg = 0x06,
+    Eq = 0x08,
+    Neq = 0x09,
+    Gr = 0x0a,
+    Ls = 0x0b,
+    Ge = 0x0c,
+    Le = 0x0d,
+    Jmp = 0x0e,
+    Jf = 0x0f,
+    Jback = 0x10,
+    Bin = 0x11,
+    Liv = 0x12,
+    Lfv = 0x13,
+    Not = 0x14,
+    Pop = 0x15,
+    Call = 0x16,
+    Ret = 0x17,
+    Lig = 0x18,
+    Lfg = 0x19,
+    Hcall = 0x1a,
+    Dbg = 0xfe,
+    Hlt = 0xff,
+}
+
+impl Instruction {
+    // Команда по мнемонике, без учета регистра
+    pub fn from_mnemonic(name: &str) -> Option<Self> {
+        (0..=u8::MAX)
+            .filter_map(|byte| Self::try_from(byte).ok())
+            .find(|inst| inst.mnemonic().eq_ignore_ascii_case(name))
+    }
+
+    pub fn mnemonic(&self) -> &'static str {
+        match self {
+            Self::Nop => "NOP",
+            Self::Push => "PUSH",
+            Self::Add => "ADD",
+            Self::Sub => "SUB",
+            Self::Mul => "MUL",
+            Self::Div => "DIV",
+            Self::Neg => "NEG",
+            Self::Eq => "EQ",
+            Self::Neq => "NEQ",
+            Self::Gr => "GR",
+            Self::Ls => "LS",
+            Self::Ge => "GE",
+            Self::Le => "LE",
+            Self::Jmp => "JMP",
+            Self::Jf => "JF",
+            Self::Jback => "JBACK",
+            Self::Bin => "BIN",
+            Self::Liv => "LIV",
+            Self::Lfv => "LFV",
+            Self::Not => "NOT",
+            Self::Pop => "POP",
+            Self::Call => "CALL",
+            Self::Ret => "RET",
+            Self::Lig => "LIG",
+            Self::Lfg => "LFG",
+            Self::Hcall => "HCALL",
+            Self::Dbg => "DBG",
+            Self::Hlt => "HLT",
+        }
+    }
+
+    // Размер операндов, следующих за кодом команды, в байтах
+    pub fn operands_size(&self) -> usize {
+        match self {
+            Self::Push | Self::Jmp | Self::Jf | Self::Jback | Self::Bin | Self::Hcall => 2,
+            Self::Liv | Self::Lfv | Self::Lig | Self::Lfg | Self::Call => 4,
+            _ => 0,
+        }
+    }
 }
 
 impl TryFrom<u8> for Instruction {
@@ -37,7 +97,6 @@ impl TryFrom<u8> for Instruction {
             0x04 => Ok(Instruction::Mul),
             0x05 => Ok(Instruction::Div),
             0x06 => Ok(Instruction::Neg),
-            0x07 => Ok(Instruction::Jmp),
             0x08 => Ok(Instruction::Eq),
             0x09 => Ok(Instruction::Neq),
             0x0a => Ok(Instruction::Gr),
@@ -50,9 +109,23 @@ impl TryFrom<u8> for Instruction {
             0x11 => Ok(Instruction::Bin),
             0x12 => Ok(Instruction::Liv),
             0x13 => Ok(Instruction::Lfv),
+            0x14 => Ok(Instruction::Not),
+            0x15 => Ok(Instruction::Pop),
+            0x16 => Ok(Instruction::Call),
+            0x17 => Ok(Instruction::Ret),
+            0x18 => Ok(Instruction::Lig),
+            0x19 => Ok(Instruction::Lfg),
+            0x1a => Ok(Instruction::Hcall),
             0xfe => Ok(Instruction::Dbg),
             0xff => Ok(Instruction::Hlt),
             _ => Err(InterpretationError::OpcodeError),
         }
     }
 }
+
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        // pad позволяет выравнивать мнемоники в листинге через {:<N}
+        f.pad(self.mnemonic())
+    }
+}
@@ -0,0 +1,273 @@
+use crate::parser::tokens::BIn;
+use crate::vm::errors::*;
+use crate::vm::host::HostFunctions;
+use crate::vm::instruction::Instruction;
+use crate::vm::vm::{Bytecode, MAX_VARIABLES};
+use std::collections::{HashMap, HashSet};
+
+#[derive(Clone, Copy)]
+struct Op {
+    inst: Instruction,
+    operand: u32,
+    size: usize,
+}
+
+// Проверяет байткод перед выполнением, чтобы неверный файл давал
+// BytecodeError, а не ошибку или панику посреди работы программы:
+// - все команды известны и у них есть все операнды;
+// - переходы и вызовы ведут на начало команды внутри кода;
+// - индексы констант, номера встроенных функций и функций приложения существуют,
+//   номера переменных меньше MAX_VARIABLES;
+// - адреса функций в отладочной информации указывают на начало команды;
+// - на каждую команду выполнение приходит с одной и той же глубиной стека,
+//   основная программа не снимает значений с пустого стека
+//   и не доходит до конца кода без HLT.
+// Глубина стека внутри функции считается от начала функции и может быть
+// отрицательной: функция забирает аргументы со стека вызывающего
+pub struct Verifier<'a> {
+    bytecode: &'a Bytecode,
+    // Число параметров каждой функции приложения
+    host_arities: Vec<usize>,
+    ops: HashMap<usize, Op>,
+}
+
+impl<'a> Verifier<'a> {
+    pub fn new(bytecode: &'a Bytecode) -> Self {
+        Self {
+            bytecode,
+            host_arities: vec![],
+            ops: HashMap::new(),
+        }
+    }
+
+    pub fn with_host_functions(mut self, host_functions: &HostFunctions) -> Self {
+        self.host_arities = host_functions.iter().map(|f| f.arity).collect();
+        self
+    }
+
+    pub fn verify(mut self) -> Result<(), BytecodeError> {
+        self.decode()?;
+        let mut offsets: Vec<usize> = self.ops.keys().copied().collect();
+        offsets.sort();
+        let mut functions = HashSet::new();
+        for &offset in &offsets {
+            let op = self.ops[&offset];
+            if let Some(target) = self.target(offset, op)? {
+                if op.inst == Instruction::Call {
+                    functions.insert(target);
+                }
+            }
+            self.check_operand(offset, op)?;
+        }
+        if let Some(debug) = &self.bytecode.debug {
+            for address in debug.function_addresses() {
+                if !self.ops.contains_key(&address) {
+                    return Err(BytecodeError::BadDebugFunction(address));
+                }
+            }
+        }
+
+        // Сколько значений снимает и сколько кладет на стек вызов каждой
+        // функции. Это известно, когда найден хотя бы один RET функции, а с
+        // каждым новым известным вызовом внутри функции она может снимать
+        // больше, поэтому функции проверяются заново, пока что-то меняется.
+        // Как в алгоритме Беллмана-Форда, если изменения не прекращаются за
+        // 2 * (число функций + 1) проходов, то рекурсия забирает со стека
+        // вызывающего все больше значений
+        let mut effects = HashMap::new();
+        let mut changing = 0;
+        for _ in 0..2 * (functions.len() + 1) {
+            self.stack_depths(0, true, &effects)?;
+            let mut changed = false;
+            for &function in &functions {
+                if let Some(effect) = self.stack_depths(function, false, &effects)? {
+                    if effects.insert(function, effect) != Some(effect) {
+                        changed = true;
+                        changing = function;
+                    }
+                }
+            }
+            if !changed {
+                return Ok(());
+            }
+        }
+        Err(BytecodeError::StackUnderflow(changing))
+    }
+
+    fn decode(&mut self) -> Result<(), BytecodeError> {
+        let code = &self.bytecode.code;
+        let mut offset = 0;
+        while offset < code.len() {
+            let byte = code[offset];
+            let inst = Instruction::try_from(byte)
+                .map_err(|_| BytecodeError::UnknownOpcode(offset, byte))?;
+            let size = 1 + inst.operands_size();
+            let operands = code
+                .get((offset + 1)..(offset + size))
+                .ok_or(BytecodeError::TruncatedInstruction(offset))?;
+            let mut bytes = [0; 4];
+            bytes[..operands.len()].copy_from_slice(operands);
+            let op = Op {
+                inst,
+                operand: u32::from_le_bytes(bytes),
+                size,
+            };
+            self.ops.insert(offset, op);
+            offset += size;
+        }
+        Ok(())
+    }
+
+    // Адрес перехода или вызова. Он должен быть началом команды
+    fn target(&self, offset: usize, op: Op) -> Result<Option<usize>, BytecodeError> {
+        let operand = op.operand as usize;
+        let target = match op.inst {
+            Instruction::Jmp | Instruction::Jf => offset + operand,
+            Instruction::Jback => offset
+                .checked_sub(operand)
+                .ok_or(BytecodeError::BadJumpTarget(offset))?,
+            Instruction::Call => operand,
+            _ => return Ok(None),
+        };
+        match self.ops.contains_key(&target) {
+            true => Ok(Some(target)),
+            false => Err(BytecodeError::BadJumpTarget(offset)),
+        }
+    }
+
+    fn jump_target(&self, offset: usize, op: Op) -> Result<usize, BytecodeError> {
+        self.target(offset, op)?
+            .ok_or(BytecodeError::BadJumpTarget(offset))
+    }
+
+    fn check_operand(&self, offset: usize, op: Op) -> Result<(), BytecodeError> {
+        let operand = op.operand as u16;
+        match op.inst {
+            Instruction::Push if operand as usize >= self.bytecode.consts.len() => {
+                Err(BytecodeError::BadConstIndex(offset, operand))
+            }
+            Instruction::Bin if BIn::from_number(operand).is_none() => {
+                Err(BytecodeError::UnknownBuiltin(offset, operand))
+            }
+            Instruction::Hcall if operand as usize >= self.host_arities.len() => {
+                Err(BytecodeError::UnknownHostFunction(offset, operand))
+            }
+            Instruction::Liv | Instruction::Lfv | Instruction::Lig | Instruction::Lfg
+                if op.operand as usize >= MAX_VARIABLES =>
+            {
+                Err(BytecodeError::BadVariableIndex(offset, op.operand))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    // Сколько значений команда снимает со стека и сколько помещает.
+    // None - вызов функции, для которой изменение глубины еще неизвестно
+    fn stack_effect(
+        &self,
+        offset: usize,
+        op: Op,
+        effects: &HashMap<usize, (i64, i64)>,
+    ) -> Result<Option<(i64, i64)>, BytecodeError> {
+        let operand = op.operand as u16;
+        let effect = match op.inst {
+            Instruction::Nop | Instruction::Jmp | Instruction::Jback | Instruction::Hlt => (0, 0),
+            Instruction::Push | Instruction::Lfv | Instruction::Lfg => (0, 1),
+            Instruction::Add
+            | Instruction::Sub
+            | Instruction::Mul
+            | Instruction::Div
+            | Instruction::Eq
+            | Instruction::Neq
+            | Instruction::Gr
+            | Instruction::Ls
+            | Instruction::Ge
+            | Instruction::Le => (2, 1),
+            Instruction::Neg | Instruction::Not => (1, 1),
+            Instruction::Jf
+            | Instruction::Liv
+            | Instruction::Lig
+            | Instruction::Pop
+            | Instruction::Dbg
+            | Instruction::Ret => (1, 0),
+            Instruction::Bin => {
+                let bin = BIn::from_number(operand)
+                    .ok_or(BytecodeError::UnknownBuiltin(offset, operand))?;
+                let pushes = !matches!(bin, BIn::Println | BIn::Exit) as i64;
+                (bin.arity() as i64, pushes)
+            }
+            Instruction::Hcall => {
+                let arity = self
+                    .host_arities
+                    .get(operand as usize)
+                    .ok_or(BytecodeError::UnknownHostFunction(offset, operand))?;
+                (*arity as i64, 1)
+            }
+            Instruction::Call => match effects.get(&(op.operand as usize)) {
+                Some(&effect) => effect,
+                None => return Ok(None),
+            },
+        };
+        Ok(Some(effect))
+    }
+
+    // Обходит все пути от entry и проверяет, что глубина стека на каждой команде
+    // не зависит от пути. Возвращает, сколько значений вызов функции снимает
+    // со стека вызывающего (наименьшая глубина внутри функции) и сколько кладет,
+    // или None, если RET не найден
+    fn stack_depths(
+        &self,
+        entry: usize,
+        main: bool,
+        effects: &HashMap<usize, (i64, i64)>,
+    ) -> Result<Option<(i64, i64)>, BytecodeError> {
+        let mut depths: HashMap<usize, i64> = HashMap::new();
+        let mut ret = None;
+        let mut lowest = 0;
+        let mut queue = vec![(entry, 0)];
+        while let Some((offset, depth)) = queue.pop() {
+            let Some(&op) = self.ops.get(&offset) else {
+                return Err(BytecodeError::FallsOffEnd(offset));
+            };
+            match depths.get(&offset) {
+                Some(&known) if known == depth => continue,
+                Some(_) => return Err(BytecodeError::StackMismatch(offset)),
+                None => depths.insert(offset, depth),
+            };
+            let Some((pops, pushes)) = self.stack_effect(offset, op, effects)? else {
+                continue;
+            };
+            if main && depth < pops {
+                return Err(BytecodeError::StackUnderflow(offset));
+            }
+            lowest = lowest.min(depth - pops);
+            let next_depth = depth - pops + pushes;
+            let next = offset + op.size;
+            let falls_through = !matches!(
+                op.inst,
+                Instruction::Hlt | Instruction::Ret | Instruction::Jmp | Instruction::Jback
+            );
+            if falls_through && next == self.bytecode.code.len() {
+                return Err(BytecodeError::FallsOffEnd(offset));
+            }
+            match op.inst {
+                Instruction::Hlt => {}
+                Instruction::Ret => match ret {
+                    Some(known) if known != depth => {
+                        return Err(BytecodeError::StackMismatch(offset))
+                    }
+                    _ => ret = Some(depth),
+                },
+                Instruction::Jmp | Instruction::Jback => {
+                    queue.push((self.jump_target(offset, op)?, next_depth))
+                }
+                Instruction::Jf => {
+                    queue.push((self.jump_target(offset, op)?, next_depth));
+                    queue.push((next, next_depth));
+                }
+                _ => queue.push((next, next_depth)),
+            }
+        }
+        Ok(ret.map(|ret| (-lowest, ret - lowest)))
+    }
+}
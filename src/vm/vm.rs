@@ -1,90 +1,405 @@
+use crate::parser::tokens::{BIn, Loc};
+use crate::vm::debug::DebugInfo;
 use crate::vm::errors::*;
+use crate::vm::host::HostFunctions;
 use crate::vm::instruction::*;
+use crate::vm::verifier::Verifier;
+use std::fmt;
+use std::io::{self, BufRead, Write};
+use std::rc::Rc;
 
-pub type Value = f64;
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value {
+    Number(f64),
+    Bool(bool),
+    Nil,
+    Str(Rc<str>),
+}
+
+impl Value {
+    // Ложью считаются false, nil и 0, все остальное - истина
+    pub fn is_truthy(&self) -> bool {
+        !matches!(self, Self::Bool(false) | Self::Nil | Self::Number(0f64))
+    }
+
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Self::Number(_) => "number",
+            Self::Bool(_) => "bool",
+            Self::Nil => "nil",
+            Self::Str(_) => "string",
+        }
+    }
+}
+
+impl fmt::Display for Value {
+    // {:#} выводит строки в кавычках, как они записываются в программе
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Number(n) => write!(f, "{n}"),
+            Self::Bool(b) => write!(f, "{b}"),
+            Self::Nil => write!(f, "nil"),
+            Self::Str(s) if f.alternate() => write!(f, "{s:?}"),
+            Self::Str(s) => write!(f, "{s}"),
+        }
+    }
+}
 
 #[macro_use]
 mod vm_macros {
+    // Арифметика определена только для чисел
     macro_rules! exec_binop {
+        ($vm:ident, $op:tt, $result:path) => {
+            let a = $vm.pop_stack()?;
+            let b = $vm.pop_stack()?;
+            match (&b, &a) {
+                (Value::Number(x), Value::Number(y)) => $vm.stack.push($result(*x $op *y)),
+                _ => {
+                    return Err(InterpretationError::BinaryTypeError(
+                        stringify!($op),
+                        b.type_name(),
+                        a.type_name(),
+                    ))
+                }
+            }
+            $vm.pc += 1;
+        };
+    }
+
+    // Сравнивать можно два числа или две строки (лексикографически)
+    macro_rules! exec_compare {
         ($vm:ident, $op:tt) => {
             let a = $vm.pop_stack()?;
             let b = $vm.pop_stack()?;
-            $vm.stack.push((b $op a).into());
+            let result = match (&b, &a) {
+                (Value::Number(x), Value::Number(y)) => *x $op *y,
+                (Value::Str(x), Value::Str(y)) => x $op y,
+                _ => {
+                    return Err(InterpretationError::BinaryTypeError(
+                        stringify!($op),
+                        b.type_name(),
+                        a.type_name(),
+                    ))
+                }
+            };
+            $vm.stack.push(Value::Bool(result));
             $vm.pc += 1;
         };
     }
 }
 
+// Формат файла байткода описан в docs/bytecode.txt
+pub const MAGIC: [u8; 4] = *b"HMR\0";
+pub const VERSION: u16 = 2;
+
+pub const SECTION_CODE: u8 = 0x01;
+pub const SECTION_CONSTS: u8 = 0x02;
+pub const SECTION_DEBUG: u8 = 0x03;
+
+const CONST_NUMBER: u8 = 0x00;
+const CONST_BOOL: u8 = 0x01;
+const CONST_NIL: u8 = 0x02;
+const CONST_STRING: u8 = 0x03;
+
+pub struct Bytecode {
+    pub code: Vec<u8>,
+    pub consts: Vec<Value>,
+    pub debug: Option<DebugInfo>,
+}
+
+impl Bytecode {
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, BytecodeError> {
+        let mut reader = ByteReader::new(bytes);
+        if reader.take(MAGIC.len())? != MAGIC {
+            return Err(BytecodeError::BadMagic);
+        }
+        let version = reader.u16()?;
+        if version != VERSION {
+            return Err(BytecodeError::UnsupportedVersion(version));
+        }
+        let section_count = reader.u16()?;
+        let mut code = None;
+        let mut consts = None;
+        let mut debug = None;
+        for _ in 0..section_count {
+            let kind = reader.u8()?;
+            let len = reader.u32()? as usize;
+            let data = reader.take(len)?;
+            let slot = match kind {
+                SECTION_CODE => &mut code,
+                SECTION_CONSTS => &mut consts,
+                SECTION_DEBUG => &mut debug,
+                _ => return Err(BytecodeError::UnknownSection(kind)),
+            };
+            if slot.replace(data.to_vec()).is_some() {
+                return Err(BytecodeError::DuplicateSection(kind));
+            }
+        }
+        if !reader.is_empty() {
+            return Err(BytecodeError::TrailingData);
+        }
+        let code = code.ok_or(BytecodeError::MissingSection(SECTION_CODE))?;
+        let consts = parse_const_table(&consts.unwrap_or_default())?;
+        let debug = debug
+            .map(|debug| DebugInfo::from_bytes(&debug))
+            .transpose()?;
+        Ok(Self {
+            code,
+            consts,
+            debug,
+        })
+    }
+
+    pub fn to_bytes(&self) -> Result<Vec<u8>, BytecodeError> {
+        let mut const_table = vec![];
+        for c in &self.consts {
+            let (val_type, data) = match c {
+                Value::Number(n) => (CONST_NUMBER, f64::to_le_bytes(*n).to_vec()),
+                Value::Bool(b) => (CONST_BOOL, vec![*b as u8]),
+                Value::Nil => (CONST_NIL, vec![]),
+                Value::Str(s) => (CONST_STRING, s.as_bytes().to_vec()),
+            };
+            const_table.push(val_type);
+            const_table.extend_from_slice(&u32::to_le_bytes(data.len() as u32));
+            const_table.extend_from_slice(&data);
+        }
+        let mut sections = vec![
+            (SECTION_CODE, self.code.clone()),
+            (SECTION_CONSTS, const_table),
+        ];
+        if let Some(debug) = &self.debug {
+            sections.push((SECTION_DEBUG, debug.to_bytes()?));
+        }
+
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend_from_slice(&u16::to_le_bytes(VERSION));
+        bytes.extend_from_slice(&u16::to_le_bytes(sections.len() as u16));
+        for (kind, data) in sections {
+            bytes.push(kind);
+            bytes.extend_from_slice(&u32::to_le_bytes(data.len() as u32));
+            bytes.extend_from_slice(&data);
+        }
+        Ok(bytes)
+    }
+}
+
+fn parse_const_table(const_table: &[u8]) -> Result<Vec<Value>, BytecodeError> {
+    let mut consts = vec![];
+    let mut reader = ByteReader::new(const_table);
+    while !reader.is_empty() {
+        let val_type = reader.u8()?;
+        let size = reader.u32()?;
+        let value_bytes = reader.take(size as usize)?;
+        let value = match (val_type, value_bytes) {
+            (CONST_NUMBER, bytes) => Value::Number(f64::from_le_bytes(
+                bytes.try_into().map_err(|_| BytecodeError::IncorrectRep)?,
+            )),
+            (CONST_BOOL, [0]) => Value::Bool(false),
+            (CONST_BOOL, [1]) => Value::Bool(true),
+            (CONST_NIL, []) => Value::Nil,
+            (CONST_STRING, bytes) => Value::Str(
+                std::str::from_utf8(bytes)
+                    .map_err(|_| BytecodeError::IncorrectRep)?
+                    .into(),
+            ),
+            _ => return Err(BytecodeError::IncorrectRep),
+        };
+        consts.push(value);
+    }
+    Ok(consts)
+}
+
+pub(crate) struct ByteReader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> ByteReader<'a> {
+    pub(crate) fn new(bytes: &'a [u8]) -> Self {
+        Self { bytes, pos: 0 }
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.pos >= self.bytes.len()
+    }
+
+    pub(crate) fn take(&mut self, len: usize) -> Result<&'a [u8], BytecodeError> {
+        let data = self
+            .bytes
+            .get(self.pos..(self.pos + len))
+            .ok_or(BytecodeError::UnexpectedEof)?;
+        self.pos += len;
+        Ok(data)
+    }
+
+    pub(crate) fn u8(&mut self) -> Result<u8, BytecodeError> {
+        Ok(self.take(1)?[0])
+    }
+
+    pub(crate) fn u16(&mut self) -> Result<u16, BytecodeError> {
+        let bytes = self.take(2)?;
+        Ok(u16::from_le_bytes([bytes[0], bytes[1]]))
+    }
+
+    pub(crate) fn u32(&mut self) -> Result<u32, BytecodeError> {
+        let bytes = self.take(4)?;
+        Ok(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+}
+
+// Предельная глубина вложенности вызовов функций
+const MAX_FRAMES: usize = 1024;
+// Предельное число переменных в основной программе и в каждом вызове функции
+pub const MAX_VARIABLES: usize = 1 << 16;
+
+struct Frame {
+    // Адрес начала вызванной функции
+    function: usize,
+    return_pc: usize,
+    variables: Vec<Option<Value>>,
+}
+
 pub struct VM {
     stack: Vec<Value>,
     program: Vec<u8>,
     consts: Vec<Value>,
-    variables: Vec<Value>,
+    debug: Option<DebugInfo>,
+    // None - переменная объявлена, но еще не получила значения
+    variables: Vec<Option<Value>>,
+    frames: Vec<Frame>,
     pc: usize,
     running: bool,
+    // Куда выводят println и DBG
+    output: Box<dyn Write>,
+    // Откуда читает readln. None - стандартный ввод
+    input: Option<Box<dyn BufRead>>,
+    host_functions: HostFunctions,
+    // Код, переданный в exit. None, если программа не вызывала exit
+    exit_code: Option<u8>,
 }
 
 impl VM {
+    // Байткод из файла проверяется перед выполнением
     pub fn new(bytecode: Vec<u8>) -> Result<Self, BytecodeError> {
-        let mut bytecode_iter = bytecode.into_iter();
-        let program: Vec<u8> = bytecode_iter
-            .by_ref()
-            .take_while(|c| *c != 0xff)
-            .chain([0xff])
-            .collect();
-        let const_table: Vec<u8> = bytecode_iter.collect();
-        let mut vm = VM {
+        let bytecode = Bytecode::from_bytes(&bytecode)?;
+        Verifier::new(&bytecode).verify()?;
+        Ok(Self::from_bytecode(bytecode))
+    }
+
+    pub fn from_bytecode(bytecode: Bytecode) -> Self {
+        VM {
             stack: vec![],
-            program,
-            consts: vec![],
+            program: bytecode.code,
+            consts: bytecode.consts,
+            debug: bytecode.debug,
             variables: vec![],
+            frames: vec![],
             pc: 0,
             running: false,
-        };
-        vm.parse_const_table(const_table)?;
-        Ok(vm)
-    }
-
-    fn parse_const_table(&mut self, const_table: Vec<u8>) -> Result<(), BytecodeError> {
-        let len = const_table.len();
-        let mut i = 0;
-        while (i < len) {
-            let val_type = const_table
-                .get(i)
-                .ok_or(BytecodeError::UnexpectedEof)?;
-            let size = *const_table
-                .get(i + 1)
-                .ok_or(BytecodeError::UnexpectedEof)?;
-            let value_bytes = const_table
-                .get((i + 2)..(i + 2 + size as usize))
-                .ok_or(BytecodeError::UnexpectedEof)?;
-            let value = f64::from_le_bytes(
-                value_bytes
-                    .try_into()
-                    .map_err(|_| BytecodeError::IncorrectRep)?,
-            );
-            self.consts.push(value);
-            i += 2 + size as usize;
+            output: Box::new(io::stdout()),
+            input: None,
+            host_functions: HostFunctions::new(),
+            exit_code: None,
         }
-        Ok(())
     }
 
-    pub fn load_program(&mut self, program: Vec<u8>) {
-        self.program = program;
+    pub fn set_input(&mut self, input: Box<dyn BufRead>) {
+        self.input = Some(input);
+    }
+
+    pub fn set_host_functions(&mut self, host_functions: HostFunctions) {
+        self.host_functions = host_functions;
+    }
+
+    pub fn set_output(&mut self, output: Box<dyn Write>) {
+        self.output = output;
+    }
+
+    // None, если переменной с таким номером еще не было присвоено значение
+    pub fn global(&self, idx: usize) -> Option<&Value> {
+        self.variables.get(idx)?.as_ref()
+    }
+
+    pub fn set_global(&mut self, idx: usize, val: Value) {
+        Self::store_variable(&mut self.variables, idx, val);
+    }
+
+    pub fn exit_code(&self) -> Option<u8> {
+        self.exit_code
+    }
+
+    // Заменяет программу, сохраняя значения глобальных переменных
+    pub fn load(&mut self, bytecode: Bytecode) {
+        self.program = bytecode.code;
+        self.consts = bytecode.consts;
+        self.debug = bytecode.debug;
     }
 
-    pub fn run(&mut self) -> Result<(), InterpretationError> {
+    // Выполняет программу с адреса pc. После ошибки или exit стек и кадры
+    // вызовов очищаются, чтобы можно было продолжить с другого адреса
+    pub fn run_from(&mut self, pc: usize) -> Result<(), RuntimeError> {
+        self.pc = pc;
+        let result = self.run();
+        if result.is_err() || self.exit_code.is_some() {
+            self.stack.clear();
+            self.frames.clear();
+        }
+        result
+    }
+
+    pub fn run(&mut self) -> Result<(), RuntimeError> {
         self.running = true;
+        self.exit_code = None;
         while self.running {
-            self.run_one_instr()?;
+            if let Err(error) = self.run_one_instr() {
+                self.running = false;
+                return Err(self.runtime_error(error));
+            }
         }
         Ok(())
     }
 
+    // Место ошибки и цепочка вызовов, которая к ней привела
+    fn runtime_error(&self, error: InterpretationError) -> RuntimeError {
+        let mut trace: Vec<TraceEntry> = vec![];
+        let mut pc = self.pc;
+        for frame in self.frames.iter().rev() {
+            let name = self
+                .debug
+                .as_ref()
+                .and_then(|debug| debug.function(frame.function))
+                .map(String::from)
+                .unwrap_or_else(|| format!("{:#06x}", frame.function));
+            let entry = TraceEntry {
+                function: Some(name),
+                loc: self.loc(pc),
+                repeats: 1,
+            };
+            match trace.last_mut() {
+                Some(last) if last.function == entry.function && last.loc == entry.loc => {
+                    last.repeats += 1
+                }
+                _ => trace.push(entry),
+            }
+            // Место вызова - команда CALL длиной 5 байт перед адресом возврата
+            pc = frame.return_pc - 5;
+        }
+        trace.push(TraceEntry {
+            function: None,
+            loc: self.loc(pc),
+            repeats: 1,
+        });
+        RuntimeError { error, trace }
+    }
+
+    fn loc(&self, pc: usize) -> Option<Loc> {
+        self.debug.as_ref()?.loc(pc)
+    }
+
     fn run_one_instr(&mut self) -> Result<(), InterpretationError> {
         let inst: Instruction = self.get_byte(0)?.try_into()?;
         match inst {
-            Instruction::Nop => {}
+            Instruction::Nop => self.pc += 1,
             Instruction::Push => {
                 let index: u16 = self.next_2_bytes()?;
                 let val: Value = self.get_const(index as usize)?;
@@ -92,45 +407,74 @@ impl VM {
                 self.pc += 3;
             }
             Instruction::Add => {
-                exec_binop!(self, +);
+                // Сложение строк - конкатенация
+                if let [.., Value::Str(b), Value::Str(a)] = self.stack.as_slice() {
+                    let concat = format!("{b}{a}");
+                    self.stack.truncate(self.stack.len() - 2);
+                    self.stack.push(Value::Str(concat.into()));
+                    self.pc += 1;
+                } else {
+                    exec_binop!(self, +, Value::Number);
+                }
             }
             Instruction::Sub => {
-                exec_binop!(self, -);
+                exec_binop!(self, -, Value::Number);
             }
             Instruction::Mul => {
-                exec_binop!(self, *);
+                exec_binop!(self, *, Value::Number);
             }
             Instruction::Div => {
                 let a = self.pop_stack()?;
                 let b = self.pop_stack()?;
-                if a == 0f64 {
+                let (Value::Number(x), Value::Number(y)) = (&b, &a) else {
+                    return Err(InterpretationError::BinaryTypeError(
+                        "/",
+                        b.type_name(),
+                        a.type_name(),
+                    ));
+                };
+                if *y == 0f64 {
                     return Err(InterpretationError::ZeroDivisionError);
                 }
-                self.stack.push(b / a);
+                self.stack.push(Value::Number(x / y));
                 self.pc += 1;
             }
             Instruction::Neg => {
                 let a = self.pop_stack()?;
-                self.stack.push(-a);
+                let Value::Number(n) = a else {
+                    return Err(InterpretationError::UnaryTypeError("-", a.type_name()));
+                };
+                self.stack.push(Value::Number(-n));
+                self.pc += 1;
+            }
+            Instruction::Not => {
+                let a = self.pop_stack()?;
+                self.stack.push(Value::Bool(!a.is_truthy()));
                 self.pc += 1;
             }
             Instruction::Eq => {
-                exec_binop!(self, ==);
+                let a = self.pop_stack()?;
+                let b = self.pop_stack()?;
+                self.stack.push(Value::Bool(b == a));
+                self.pc += 1;
             }
             Instruction::Neq => {
-                exec_binop!(self, !=);
+                let a = self.pop_stack()?;
+                let b = self.pop_stack()?;
+                self.stack.push(Value::Bool(b != a));
+                self.pc += 1;
             }
             Instruction::Gr => {
-                exec_binop!(self, >);
+                exec_compare!(self, >);
             }
             Instruction::Ls => {
-                exec_binop!(self, <);
+                exec_compare!(self, <);
             }
             Instruction::Ge => {
-                exec_binop!(self, >=);
+                exec_compare!(self, >=);
             }
             Instruction::Le => {
-                exec_binop!(self, <=);
+                exec_compare!(self, <=);
             }
             Instruction::Jmp => {
                 let offset: u16 = self.next_2_bytes()?;
@@ -139,7 +483,7 @@ impl VM {
             Instruction::Jf => {
                 let offset: u16 = self.next_2_bytes()?;
                 let a = self.pop_stack()?;
-                if a == 0f64 {
+                if !a.is_truthy() {
                     self.pc += offset as usize;
                 } else {
                     self.pc += 3;
@@ -151,43 +495,89 @@ impl VM {
             }
             Instruction::Bin => {
                 let func_number: u16 = self.next_2_bytes()?;
-                match func_number {
-                    // println
-                    0x0000 => {
-                        let arg = self.pop_stack()?;
-                        println!("{arg}");
-                    }
-                    0x0001 => {
-                        let arg = self.pop_stack()?;
-                        self.stack.push(f64::abs(arg));
-                    }
-                    _ => return Err(InterpretationError::UnknownBuiltin),
-                };
+                let bin =
+                    BIn::from_number(func_number).ok_or(InterpretationError::UnknownBuiltin)?;
+                self.call_builtin(bin)?;
+                self.pc += 3;
+            }
+            Instruction::Hcall => {
+                let index: u16 = self.next_2_bytes()?;
+                let function = self
+                    .host_functions
+                    .get(index as usize)
+                    .cloned()
+                    .ok_or(InterpretationError::UnknownHostFunction(index))?;
+                let first_arg = self
+                    .stack
+                    .len()
+                    .checked_sub(function.arity)
+                    .ok_or(InterpretationError::EmptyStackError)?;
+                let args = self.stack.split_off(first_arg);
+                let result = (function.func)(&args)
+                    .map_err(|e| InterpretationError::HostError(function.name, e))?;
+                self.stack.push(result);
                 self.pc += 3;
             }
             Instruction::Liv => {
                 let idx = self.next_4_bytes()? as usize;
                 let val = self.pop_stack()?;
-                match idx {
-                    idx if idx < self.variables.len() => self.variables[idx] = val,
-                    idx if idx == self.variables.len() => self.variables.push(val),
-                    _ => panic!("Ошибка"),
-                }
+                let variables = match self.frames.last_mut() {
+                    Some(frame) => &mut frame.variables,
+                    None => &mut self.variables,
+                };
+                Self::store_variable(variables, idx, val);
                 self.pc += 5;
             }
             Instruction::Lfv => {
                 let idx = self.next_4_bytes()? as usize;
-                if (idx < self.variables.len()) {
-                    let val = self.variables[idx];
-                    self.stack.push(val);
-                } else {
-                    panic!("Ошибка")
+                let variables = match self.frames.last() {
+                    Some(frame) => &frame.variables,
+                    None => &self.variables,
                 };
+                let val = Self::load_variable(variables, idx)?;
+                self.stack.push(val);
+                self.pc += 5;
+            }
+            Instruction::Lig => {
+                let idx = self.next_4_bytes()? as usize;
+                let val = self.pop_stack()?;
+                Self::store_variable(&mut self.variables, idx, val);
+                self.pc += 5;
+            }
+            Instruction::Lfg => {
+                let idx = self.next_4_bytes()? as usize;
+                let val = Self::load_variable(&self.variables, idx)?;
+                self.stack.push(val);
                 self.pc += 5;
             }
+            Instruction::Pop => {
+                self.pop_stack()?;
+                self.pc += 1;
+            }
+            Instruction::Call => {
+                let address = self.next_4_bytes()? as usize;
+                if self.frames.len() >= MAX_FRAMES {
+                    return Err(InterpretationError::StackOverflow);
+                }
+                self.frames.push(Frame {
+                    function: address,
+                    return_pc: self.pc + 5,
+                    variables: vec![],
+                });
+                self.pc = address;
+            }
+            Instruction::Ret => {
+                let val = self.pop_stack()?;
+                let frame = self
+                    .frames
+                    .pop()
+                    .ok_or(InterpretationError::ReturnOutsideFunction)?;
+                self.stack.push(val);
+                self.pc = frame.return_pc;
+            }
             Instruction::Dbg => {
                 let a = self.pop_stack()?;
-                println!("{a:#}");
+                writeln!(self.output, "{a:#}").map_err(InterpretationError::OutputError)?;
                 self.pc += 1;
             }
             Instruction::Hlt => {
@@ -197,6 +587,107 @@ impl VM {
         Ok(())
     }
 
+    // Аргументы лежат на стеке в порядке передачи, последний - на вершине
+    fn call_builtin(&mut self, bin: BIn) -> Result<(), InterpretationError> {
+        let name = bin.name();
+        let result = match bin {
+            BIn::Println => {
+                let arg = self.pop_stack()?;
+                writeln!(self.output, "{arg}").map_err(InterpretationError::OutputError)?;
+                return Ok(());
+            }
+            BIn::Abs => Value::Number(f64::abs(self.pop_number(name)?)),
+            BIn::Len => Value::Number(self.pop_string(name)?.chars().count() as f64),
+            BIn::Substr => {
+                let count = self.pop_index(name)?;
+                let start = self.pop_index(name)?;
+                let s = self.pop_string(name)?;
+                let sub: String = s.chars().skip(start).take(count).collect();
+                Value::Str(sub.into())
+            }
+            BIn::Upper => Value::Str(self.pop_string(name)?.to_uppercase().into()),
+            BIn::Lower => Value::Str(self.pop_string(name)?.to_lowercase().into()),
+            BIn::ToString => Value::Str(self.pop_stack()?.to_string().into()),
+            BIn::Readln => self.read_line()?,
+            // Код завершения - целое число от 0 до 255
+            BIn::Exit => {
+                let code = self.pop_number(name)?;
+                if !(0f64..=255f64).contains(&code) || code.fract() != 0f64 {
+                    return Err(InterpretationError::InvalidExitCode(code));
+                }
+                self.exit_code = Some(code as u8);
+                self.running = false;
+                return Ok(());
+            }
+            // Строка, не являющаяся числом, дает nil
+            BIn::ParseNumber => match self.pop_string(name)?.trim().parse() {
+                Ok(n) => Value::Number(n),
+                Err(_) => Value::Nil,
+            },
+        };
+        self.stack.push(result);
+        Ok(())
+    }
+
+    // Строка без символа перевода строки или nil, если ввод закончился
+    fn read_line(&mut self) -> Result<Value, InterpretationError> {
+        let mut line = String::new();
+        let read = match &mut self.input {
+            Some(input) => input.read_line(&mut line),
+            None => io::stdin().read_line(&mut line),
+        }
+        .map_err(InterpretationError::InputError)?;
+        if read == 0 {
+            return Ok(Value::Nil);
+        }
+        let len = line.trim_end_matches(['\n', '\r']).len();
+        line.truncate(len);
+        Ok(Value::Str(line.into()))
+    }
+
+    fn pop_number(&mut self, op: &'static str) -> Result<f64, InterpretationError> {
+        match self.pop_stack()? {
+            Value::Number(n) => Ok(n),
+            val => Err(InterpretationError::UnaryTypeError(op, val.type_name())),
+        }
+    }
+
+    fn pop_string(&mut self, op: &'static str) -> Result<Rc<str>, InterpretationError> {
+        match self.pop_stack()? {
+            Value::Str(s) => Ok(s),
+            val => Err(InterpretationError::UnaryTypeError(op, val.type_name())),
+        }
+    }
+
+    // Неотрицательное целое число, используемое как индекс или длина
+    fn pop_index(&mut self, op: &'static str) -> Result<usize, InterpretationError> {
+        let n = self.pop_number(op)?;
+        if n < 0f64 || n.fract() != 0f64 {
+            return Err(InterpretationError::InvalidArgument(op, n));
+        }
+        Ok(n as usize)
+    }
+
+    fn store_variable(variables: &mut Vec<Option<Value>>, idx: usize, val: Value) {
+        // Переменные с меньшими номерами могли быть объявлены,
+        // но еще не получить значения (например, в другой ветке if)
+        if idx >= variables.len() {
+            variables.resize(idx + 1, None);
+        }
+        variables[idx] = Some(val);
+    }
+
+    fn load_variable(
+        variables: &[Option<Value>],
+        idx: usize,
+    ) -> Result<Value, InterpretationError> {
+        variables
+            .get(idx)
+            .cloned()
+            .flatten()
+            .ok_or(InterpretationError::UnassignedVariable(idx))
+    }
+
     fn get_byte(self: &VM, offset: usize) -> Result<u8, InterpretationError> {
         self.program
             .get(self.pc + offset)
@@ -207,7 +698,7 @@ impl VM {
     fn next_2_bytes(self: &VM) -> Result<u16, InterpretationError> {
         let b1: u8 = self.get_byte(1)?;
         let b2: u8 = self.get_byte(2)?;
-        Ok(u16::from_ne_bytes([b1, b2]))
+        Ok(u16::from_le_bytes([b1, b2]))
     }
 
     fn next_4_bytes(&self) -> Result<u32, InterpretationError> {
@@ -215,19 +706,17 @@ impl VM {
         let b2: u8 = self.get_byte(2)?;
         let b3: u8 = self.get_byte(3)?;
         let b4: u8 = self.get_byte(4)?;
-        Ok(u32::from_ne_bytes([b1, b2, b3, b4]))
+        Ok(u32::from_le_bytes([b1, b2, b3, b4]))
     }
 
     fn get_const(self: &VM, index: usize) -> Result<Value, InterpretationError> {
         self.consts
             .get(index)
             .ok_or(InterpretationError::BadConstsIndexError)
-            .copied()
+            .cloned()
     }
 
     fn pop_stack(self: &mut VM) -> Result<Value, InterpretationError> {
-        self.stack
-            .pop()
-            .ok_or(InterpretationError::EmptyStackError)
+        self.stack.pop().ok_or(InterpretationError::EmptyStackError)
     }
 }
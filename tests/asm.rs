@@ -0,0 +1,113 @@
+// Программы на языке ассемблера собираются командой asm
+// и запускаются как обычный байткод
+mod common;
+
+use common::{hammer, source_file, stderr, stdout, temp_path};
+use std::fs;
+use std::path::Path;
+use std::process::Output;
+
+// Собирает файл в name и запускает результат. Возвращает вывод запуска
+fn assemble_and_run(source: &Path, name: &str) -> Output {
+    let out = temp_path(name);
+    let out = out.to_str().unwrap();
+    let assembled = hammer(&["asm", source.to_str().unwrap(), out]);
+    assert!(assembled.status.success(), "{}", stderr(&assembled));
+    let output = hammer(&["run", "-b", out]);
+    let _ = fs::remove_file(out);
+    output
+}
+
+#[test]
+fn countdown_example_runs() {
+    let example = Path::new(env!("CARGO_MANIFEST_DIR")).join("examples/countdown.hasm");
+    let output = assemble_and_run(&example, "countdown-out");
+    assert!(output.status.success());
+    assert_eq!(stdout(&output), "3\n2\n1\nготово\n");
+}
+
+#[test]
+fn instructions_the_compiler_never_emits() {
+    let file = source_file(
+        "opcodes.hasm",
+        r#"
+.const
+    2
+    "b"
+    "a"
+.code
+        nop
+        PUSH 1
+        PUSH 2
+        LE                  ; "b" <= "a"
+        DBG
+        PUSH 0
+        NEG
+        LIG 7
+        LFG 7
+        BIN abs
+        DBG
+        JMP skip
+        PUSH 0              ; пропускается
+        DBG
+skip:   PUSH 2
+        BIN 4               ; upper
+        DBG
+        HLT
+"#,
+    );
+    let output = assemble_and_run(&file, "opcodes-out");
+    assert!(output.status.success());
+    assert_eq!(stdout(&output), "false\n2\n\"A\"\n");
+    let _ = fs::remove_file(file);
+}
+
+#[test]
+fn errors_point_into_the_source() {
+    let file = source_file(
+        "asm-errors.hasm",
+        ".const\n    1\n.code\n        JF end\nstart:  PUSH 1\n        JMP start\n        CALL nowhere\nend:    HLT\n",
+    );
+    let output = hammer(&[
+        "asm",
+        "--message-format=json",
+        file.to_str().unwrap(),
+        temp_path("asm-errors-out").to_str().unwrap(),
+    ]);
+    assert_eq!(output.status.code(), Some(5));
+    let stderr = stderr(&output);
+    let codes: Vec<&str> = stderr
+        .lines()
+        .filter_map(|line| line.split(r#""code":""#).nth(1)?.split('"').next())
+        .collect();
+    assert_eq!(codes, ["H0711", "H0710", "H0707"], "{stderr}");
+    assert!(stderr.contains(r#""span":{"line":6,"col":13"#), "{stderr}");
+    let _ = fs::remove_file(file);
+}
+
+#[test]
+fn constants_follow_literal_syntax() {
+    let file = source_file(
+        "asm-consts.hasm",
+        ".const\n    \"\\u{41}\\\\\"\n.code\n    PUSH 0\n    DBG\n    HLT\n",
+    );
+    let output = assemble_and_run(&file, "asm-consts-out");
+    let _ = fs::remove_file(file);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(stdout(&output), "\"A\\\\\"\n");
+
+    for constant in ["\"\\'\"", "inf", "NaN", "1e999", "-inf"] {
+        let file = source_file(
+            "asm-bad-const.hasm",
+            &format!(".const\n    {constant}\n.code\n    HLT\n"),
+        );
+        let output = hammer(&[
+            "asm",
+            file.to_str().unwrap(),
+            temp_path("asm-bad-const-out").to_str().unwrap(),
+        ]);
+        let _ = fs::remove_file(file);
+        assert_eq!(output.status.code(), Some(5), "{constant}");
+        assert!(stderr(&output).contains("[H0706]"), "{constant}");
+    }
+}
@@ -0,0 +1,44 @@
+// Ошибки командной строки
+mod common;
+
+use common::{hammer, stderr};
+
+#[test]
+fn cli_errors_use_the_requested_message_format() {
+    let cases: [&[&str]; 4] = [
+        &["run", "--message-format=json"],
+        &["--message-format", "json", "unknown"],
+        &["compile", "--message-format=json", "--emit=text", "a.hmr"],
+        &["--lang=xx", "--message-format=json", "run", "a.hmr"],
+    ];
+    for args in cases {
+        let output = hammer(args);
+        assert_eq!(output.status.code(), Some(2), "{args:?}");
+        let stderr = stderr(&output);
+        assert_eq!(stderr.lines().count(), 1, "{args:?}: {stderr}");
+        assert!(
+            stderr.starts_with('{') && stderr.contains(r#""code":"H000"#),
+            "{args:?}: {stderr}"
+        );
+    }
+    // Без параметра сообщение выводится для человека вместе со справкой
+    let stderr = stderr(&hammer(&["run"]));
+    assert!(
+        stderr.contains("[H0003]") && stderr.contains("hammer <"),
+        "{stderr}"
+    );
+}
+
+#[test]
+fn unreadable_input_is_not_a_compile_error() {
+    for args in [
+        &["run", "-b", "no-such-file.hbc"][..],
+        &["inspect", "no-such-file.hbc"],
+        &["run", "no-such-file.hmr"],
+        &["asm", "no-such-file.hasm"],
+    ] {
+        let output = hammer(args);
+        assert_eq!(output.status.code(), Some(9), "{args:?}");
+        assert!(stderr(&output).contains("[H0008]"), "{args:?}");
+    }
+}
@@ -0,0 +1,56 @@
+// Общие функции интеграционных тестов
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output, Stdio};
+
+// Запускает hammer с сообщениями на русском
+pub fn hammer(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_hammer"))
+        .args(args)
+        .env("HAMMER_LANG", "ru")
+        .stdin(Stdio::null())
+        .output()
+        .expect("не удалось запустить hammer")
+}
+
+// Путь во временном каталоге. Номер процесса в имени разделяет параллельные
+// запуски тестов, а тесты одного процесса должны передавать разные имена
+pub fn temp_path(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("hammer-{}-{name}", std::process::id()))
+}
+
+// Временный файл с текстом source, name включает расширение
+pub fn source_file(name: &str, source: &str) -> PathBuf {
+    let path = temp_path(name);
+    fs::write(&path, source).expect("не удалось записать временный файл");
+    path
+}
+
+pub fn stdout(output: &Output) -> String {
+    String::from_utf8_lossy(&output.stdout).into_owned()
+}
+
+pub fn stderr(output: &Output) -> String {
+    String::from_utf8_lossy(&output.stderr).into_owned()
+}
+
+// Примеры, которые показывают ошибку компиляции
+pub const FAILING_EXAMPLES: &[&str] = &["swap.hmr"];
+
+// Программы из каталога examples
+pub fn examples() -> Vec<PathBuf> {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("examples");
+    let mut examples: Vec<PathBuf> = fs::read_dir(dir)
+        .expect("нет каталога examples")
+        .map(|entry| entry.expect("ошибка чтения каталога").path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "hmr"))
+        .collect();
+    examples.sort();
+    examples
+}
+
+pub fn is_failing_example(path: &Path) -> bool {
+    path.file_name()
+        .is_some_and(|name| FAILING_EXAMPLES.iter().any(|failing| name == *failing))
+}
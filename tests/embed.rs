@@ -0,0 +1,211 @@
+// Программы компилируются и запускаются через библиотеку, без исполняемого файла
+use hammer::{CapturedOutput, HammerError, HostFunctions, Program, Runtime, Value};
+use std::error::Error;
+use std::io::Cursor;
+
+fn run(source: &str) -> Result<(Runtime, CapturedOutput), HammerError> {
+    let program = Program::compile("script", source)?;
+    let output = CapturedOutput::new();
+    let mut runtime = Runtime::new(program).with_output(output.clone());
+    runtime.run()?;
+    Ok((runtime, output))
+}
+
+#[test]
+fn program_output_and_globals() -> Result<(), Box<dyn Error>> {
+    let (mut runtime, output) =
+        run("let x = 40 + 2; let s = \"a\" + \"b\"; println(x); println(s);")?;
+    assert_eq!(output.contents(), "42\nab\n");
+    assert_eq!(runtime.global("x"), Some(Value::Number(42.0)));
+    assert_eq!(runtime.global("s"), Some(Value::Str("ab".into())));
+    assert_eq!(runtime.global("nothing"), None);
+    assert_eq!(runtime.exit_code(), None);
+
+    runtime.set_global("x", Value::Bool(true))?;
+    assert_eq!(runtime.global("x"), Some(Value::Bool(true)));
+    let error = runtime.set_global("nothing", Value::Nil).unwrap_err();
+    assert_eq!(error.exit_code(), 8);
+    assert!(error.to_string().contains("H0601"), "{error}");
+    Ok(())
+}
+
+#[test]
+fn unassigned_global_is_none() {
+    let (runtime, _) = run("let a; let b; if false { a = 1; } b = 2;").unwrap();
+    assert_eq!(runtime.global("a"), None);
+    assert_eq!(runtime.global("b"), Some(Value::Number(2.0)));
+}
+
+#[test]
+fn input_and_exit_code() {
+    let program =
+        Program::compile("script", "let name = readln(); println(name); exit(3);").unwrap();
+    let output = CapturedOutput::new();
+    let mut runtime = Runtime::new(program)
+        .with_input(Cursor::new("Hammer\n"))
+        .with_output(output.clone());
+    runtime.run().unwrap();
+    assert_eq!(output.contents(), "Hammer\n");
+    assert_eq!(runtime.exit_code(), Some(3));
+}
+
+#[test]
+fn errors_carry_codes() {
+    let cases = [
+        ("let x = ;", 4, "H02"),
+        ("let x = 1 / (2 - 2);", 5, "H0311"),
+        ("let z = 0; let x = 1 / z;", 7, "H0405"),
+    ];
+    for (source, exit_code, code) in cases {
+        let error = run(source).err().expect(source);
+        assert_eq!(error.exit_code(), exit_code, "{source}: {error:?}");
+        assert!(error.code().starts_with(code), "{source}: {error}");
+        assert!(
+            error.to_string().contains(error.code()),
+            "{source}: {error}"
+        );
+    }
+}
+
+#[test]
+fn lex_and_parse_errors_are_reported_together() {
+    let error = run("let s = \"a\\q\";\nlet = 3;").err().unwrap();
+    assert_eq!(error.exit_code(), 3);
+    let text = error.to_string();
+    assert!(text.contains("H0104") && text.contains("H0207"), "{text}");
+}
+
+#[test]
+fn compiled_program_is_a_bytecode_file() {
+    let program = Program::compile("script", "println(1);").unwrap();
+    assert!(program.to_bytes().unwrap().starts_with(b"HMR\0"));
+}
+
+fn host_functions() -> HostFunctions {
+    let mut host = HostFunctions::new();
+    host.register("add", 2, |args| match args {
+        [Value::Number(a), Value::Number(b)] => Ok(Value::Number(a + b)),
+        _ => Err(String::from("add: нужны числа")),
+    })
+    .unwrap()
+    .register("greet", 0, |_| Ok(Value::Str("hi".into())))
+    .unwrap();
+    host
+}
+
+fn run_with_host(source: &str) -> Result<String, HammerError> {
+    let program = Program::compile_with("script", source, &host_functions())?;
+    let output = CapturedOutput::new();
+    Runtime::new(program).with_output(output.clone()).run()?;
+    Ok(output.contents())
+}
+
+#[test]
+fn host_functions_are_called() {
+    let output = run_with_host("println(add(1, add(2, 3))); println(greet());").unwrap();
+    assert_eq!(output, "6\nhi\n");
+
+    let error = run_with_host("add(1);").unwrap_err();
+    assert!(error.to_string().contains("H0309"), "{error}");
+    let error = run_with_host("add(1, \"2\");").unwrap_err();
+    assert_eq!(error.exit_code(), 7);
+    assert!(error.to_string().contains("H0415"), "{error}");
+}
+
+#[test]
+fn bytecode_with_host_calls_is_loaded_with_the_same_functions() {
+    let host = host_functions();
+    let source = "println(add(1, 2));";
+    let bytes = Program::compile_with("script", source, &host)
+        .unwrap()
+        .to_bytes()
+        .unwrap();
+    let program = Program::from_bytes(&bytes, &host).unwrap();
+    let output = CapturedOutput::new();
+    Runtime::new(program)
+        .with_output(output.clone())
+        .run()
+        .unwrap();
+    assert_eq!(output.contents(), "3\n");
+
+    // Без функций приложения HCALL ссылается на несуществующую функцию
+    let error = Program::from_bytes(&bytes, &HostFunctions::new())
+        .err()
+        .unwrap();
+    assert_eq!(error.exit_code(), 6);
+    assert!(error.to_string().contains("H0514"), "{error}");
+    let error = Program::from_bytes(b"HMR", &host).err().unwrap();
+    assert!(error.to_string().contains("H0501"), "{error}");
+}
+
+#[test]
+fn registering_a_name_again_replaces_the_function() {
+    let mut host = host_functions();
+    host.register("add", 2, |_| Ok(Value::Nil)).unwrap();
+    assert_eq!(host.iter().count(), 2);
+    let program = Program::compile_with("script", "println(add(1, 2));", &host).unwrap();
+    let output = CapturedOutput::new();
+    Runtime::new(program)
+        .with_output(output.clone())
+        .run()
+        .unwrap();
+    assert_eq!(output.contents(), "nil\n");
+}
+
+#[test]
+fn unreachable_host_names_are_rejected() {
+    let mut host = HostFunctions::new();
+    for name in ["println", "abs", "let", "true", "1x", "a b", ""] {
+        let error = host.register(name, 0, |_| Ok(Value::Nil)).err();
+        let error = error.unwrap_or_else(|| panic!("{name:?} зарегистрировано"));
+        assert!(error.to_string().contains("H0602"), "{name:?}: {error}");
+    }
+    assert_eq!(host.iter().count(), 0);
+}
+
+#[test]
+fn host_function_count_fits_the_operand() {
+    let mut host = HostFunctions::new();
+    for i in 0..=u16::MAX {
+        host.register(&format!("f{i}"), 0, |_| Ok(Value::Nil))
+            .unwrap();
+    }
+    let error = host.register("extra", 0, |_| Ok(Value::Nil)).err().unwrap();
+    assert!(error.to_string().contains("H0603"), "{error}");
+    // Замена уже зарегистрированной функции номер не занимает
+    host.register("f0", 1, |_| Ok(Value::Nil)).unwrap();
+}
+
+#[test]
+fn names_too_long_for_debug_info() {
+    let name = "f".repeat(70000);
+    let program = Program::compile("script", &format!("fn {name}() {{}} {name}();")).unwrap();
+    let error = program.to_bytes().unwrap_err();
+    assert_eq!(error.exit_code(), 6);
+    assert!(error.to_string().contains("H0518"), "{error}");
+}
+
+#[test]
+fn visible_variables_are_limited() {
+    let source = |count: usize| {
+        (0..count)
+            .map(|i| format!("let v{i} = 0;"))
+            .collect::<String>()
+    };
+    assert!(Program::compile("script", &source(65536)).is_ok());
+    let error = Program::compile("script", &source(65537)).err().unwrap();
+    assert_eq!(error.exit_code(), 5);
+    assert!(error.to_string().contains("H0312"), "{error}");
+    // Номера переменных из закончившейся области видимости используются заново
+    let block = format!("{{ {} }}", source(40000));
+    assert!(Program::compile("script", &format!("{block} {block}")).is_ok());
+}
+
+#[test]
+fn repeated_calls_are_collapsed_in_the_trace() {
+    let error = run("fn f(n) { return f(n); } f(1);").err().unwrap();
+    let text = error.to_string();
+    assert!(text.contains("H0407"), "{text}");
+    assert!(text.contains("1024"), "{text}");
+    assert!(text.lines().count() < 10, "{text}");
+}
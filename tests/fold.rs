@@ -0,0 +1,108 @@
+// Свертка константных выражений и общая таблица констант. Проверяется
+// листинг скомпилированной программы, который выводит команда inspect
+mod common;
+
+use common::{hammer, source_file, stderr, stdout, temp_path};
+use std::fs;
+
+// Мнемоники команд и записи констант из листинга
+struct Listing {
+    code: Vec<String>,
+    consts: Vec<String>,
+}
+
+fn compile(name: &str, source: &str) -> Listing {
+    let file = source_file(&format!("{name}.hmr"), source);
+    let out = temp_path(&format!("{name}.hbc"));
+    let (file_path, out_path) = (file.to_str().unwrap(), out.to_str().unwrap());
+    let compiled = hammer(&["compile", file_path, out_path]);
+    assert!(compiled.status.success(), "{name}: {}", stderr(&compiled));
+    let listing = stdout(&hammer(&["inspect", out_path]));
+    let _ = fs::remove_file(file);
+    let _ = fs::remove_file(out);
+
+    let (code, consts) = listing
+        .split_once("Константы")
+        .expect("в листинге нет таблицы констант");
+    let code = code
+        .lines()
+        .filter_map(|line| {
+            line.split_whitespace()
+                .find(|word| word.chars().all(|c| c.is_ascii_uppercase()))
+        })
+        .map(String::from)
+        .collect();
+    let consts = consts
+        .lines()
+        .skip(1)
+        .filter_map(|line| Some(line.trim().split_once(' ')?.1.trim().to_string()))
+        .collect();
+    Listing { code, consts }
+}
+
+fn compile_error(name: &str, source: &str) -> String {
+    let file = source_file(&format!("{name}.hmr"), source);
+    let out = temp_path(&format!("{name}.hbc"));
+    let output = hammer(&["compile", file.to_str().unwrap(), out.to_str().unwrap()]);
+    let _ = fs::remove_file(file);
+    assert_eq!(output.status.code(), Some(5), "{name}");
+    stderr(&output)
+}
+
+#[test]
+fn literal_subtrees_are_folded() {
+    let listing = compile("fold", "println(1 + 2 * 3 - abs(-4));");
+    assert_eq!(listing.code, ["PUSH", "BIN", "HLT"]);
+    assert_eq!(listing.consts, ["3"]);
+
+    let listing = compile(
+        "fold-strings",
+        "println(\"a\" + \"b\" == \"ab\" && !false);",
+    );
+    assert_eq!(listing.code, ["PUSH", "BIN", "HLT"]);
+    assert_eq!(listing.consts, ["true"]);
+
+    // Переменная не сворачивается, но константы вокруг нее сворачиваются
+    let listing = compile("fold-partial", "let x = 1; println(x + (2 + 3));");
+    assert_eq!(
+        listing.code,
+        ["PUSH", "LIV", "LFV", "PUSH", "ADD", "BIN", "HLT"]
+    );
+    assert_eq!(listing.consts, ["1", "5"]);
+}
+
+#[test]
+fn constants_are_interned_by_value() {
+    let listing = compile(
+        "intern",
+        "let a = 1; let b = 1; let c = \"s\"; let d = \"s\"; let e = 1 == 1; let f = true; let g = 2 - 1;",
+    );
+    assert_eq!(listing.consts, ["1", "\"s\"", "true"]);
+}
+
+#[test]
+fn zero_and_negative_zero_are_different_constants() {
+    let listing = compile("zeros", "let a = 0; let b = -0; let c = 0 * -1; let d = 0;");
+    assert_eq!(listing.consts, ["0", "-0"]);
+}
+
+#[test]
+fn type_errors_are_left_to_the_vm() {
+    let listing = compile("type-error", "println(-\"s\");");
+    assert_eq!(listing.code, ["PUSH", "NEG", "BIN", "HLT"]);
+}
+
+#[test]
+fn division_by_literal_zero_is_a_compile_error() {
+    for source in [
+        "println(1 / 0);",
+        "println(1 / (2 - 2));",
+        "let x = 5; println(x / -0);",
+    ] {
+        let stderr = compile_error("div-zero", source);
+        assert!(stderr.contains("[H0311]"), "{source}: {stderr}");
+    }
+    // Делитель, известный только при выполнении, проверяет VM
+    let listing = compile("div-var", "let z = 0; println(1 / z);");
+    assert!(listing.code.contains(&String::from("DIV")));
+}
@@ -0,0 +1,106 @@
+// Промежуточное представление (compile --emit=ir) и его кодирование в байткод
+mod common;
+
+use common::{examples, hammer, is_failing_example, source_file, stderr, stdout, temp_path};
+use std::fs;
+use std::path::Path;
+
+fn emit_ir(file: &Path) -> std::process::Output {
+    hammer(&["compile", "--emit=ir", file.to_str().unwrap()])
+}
+
+// Мнемоники команд в листинге: первые слова строк, записанные заглавными буквами.
+// Переходы назад в IR записываются через JMP, а в байткоде - через JBACK
+fn mnemonics(listing: &str) -> Vec<String> {
+    listing
+        .lines()
+        .filter_map(|line| {
+            line.split_whitespace()
+                .find(|word| word.chars().all(|c| c.is_ascii_uppercase()))
+        })
+        .map(|word| match word {
+            "JBACK" => String::from("JMP"),
+            word => word.to_string(),
+        })
+        .collect()
+}
+
+#[test]
+fn ir_listing_shows_labels_and_locations() {
+    let file = source_file("loop.hmr", "let x = 0;\nwhile x < 2 {\n    x = x + 1;\n}\n");
+    let output = emit_ir(&file);
+    let _ = fs::remove_file(file);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(
+        stdout(&output),
+        "    ; 1:9
+    PUSH   0         ; 0
+    LIV    0
+
+L0:
+    ; 2:7
+    LFV    0
+    ; 2:11
+    PUSH   1         ; 2
+    ; 2:7
+    LS
+    JF     L1
+
+    ; 3:9
+    LFV    0
+    ; 3:13
+    PUSH   2         ; 1
+    ; 3:9
+    ADD
+    LIV    0
+    JMP    L0
+
+L1:
+    HLT
+"
+    );
+}
+
+// Кодировщик сохраняет порядок команд, а переходы на метки
+// превращаются в переходы на те же команды
+#[test]
+fn encoded_examples_match_their_ir() {
+    for example in examples() {
+        let ir = emit_ir(&example);
+        if is_failing_example(&example) {
+            assert_eq!(ir.status.code(), Some(5), "{}", example.display());
+            continue;
+        }
+        assert!(ir.status.success(), "{}", example.display());
+        let out = temp_path("encoded.hbc");
+        let out = out.to_str().unwrap();
+        let compiled = hammer(&["compile", example.to_str().unwrap(), out]);
+        assert!(compiled.status.success(), "{}", example.display());
+        let listing = stdout(&hammer(&["inspect", out]));
+        let _ = fs::remove_file(out);
+        let (code, _) = listing.split_once("Константы").unwrap();
+        assert_eq!(
+            mnemonics(&stdout(&ir)),
+            mnemonics(code),
+            "{}",
+            example.display()
+        );
+    }
+}
+
+#[test]
+fn emit_is_checked() {
+    let file = source_file("emit.hmr", "println(1);\n");
+    let path = file.to_str().unwrap();
+    let cases: [(&[&str], &str); 3] = [
+        (&["run", "--emit=ir", path], "H0004"),
+        (&["compile", "--emit=text", path], "H0007"),
+        (&["compile", "-O", "--emit=ir", path], "H0004"),
+    ];
+    for (args, code) in cases {
+        let output = hammer(args);
+        assert_eq!(output.status.code(), Some(2), "{args:?}");
+        assert!(stderr(&output).contains(&format!("[{code}]")), "{args:?}");
+    }
+    let _ = fs::remove_file(file);
+}
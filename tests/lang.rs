@@ -0,0 +1,36 @@
+// Язык сообщений по умолчанию берется из окружения: русский только
+// для русской локали
+use std::process::{Command, Stdio};
+
+fn first_line(env: &[(&str, &str)]) -> String {
+    let output = Command::new(env!("CARGO_BIN_EXE_hammer"))
+        .args(["run", "no-such-file.hmr"])
+        .env_remove("HAMMER_LANG")
+        .env_remove("LANG")
+        .envs(env.iter().copied())
+        .stdin(Stdio::null())
+        .output()
+        .expect("не удалось запустить hammer");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    stderr.lines().next().unwrap_or_default().to_string()
+}
+
+#[test]
+fn language_is_taken_from_the_environment() {
+    let cases: [(&[(&str, &str)], &str); 7] = [
+        (&[], "error"),
+        (&[("LANG", "C.UTF-8")], "error"),
+        (&[("LANG", "de_DE.UTF-8")], "error"),
+        (&[("LANG", "ru_RU.UTF-8")], "ошибка"),
+        (&[("HAMMER_LANG", "en"), ("LANG", "ru_RU.UTF-8")], "error"),
+        (&[("HAMMER_LANG", "ru"), ("LANG", "C")], "ошибка"),
+        (&[("HAMMER_LANG", "xx"), ("LANG", "ru_RU.UTF-8")], "ошибка"),
+    ];
+    for (env, prefix) in cases {
+        let line = first_line(env);
+        assert!(
+            line.starts_with(&format!("{prefix}[H0008]")),
+            "{env:?}: {line}"
+        );
+    }
+}
@@ -0,0 +1,101 @@
+// Оптимизатор не должен менять поведение программ: каждая программа
+// запускается с -O и без него, вывод и код завершения должны совпадать
+mod common;
+
+use common::{examples, hammer, is_failing_example, source_file, stderr, stdout};
+use std::fs;
+use std::path::Path;
+
+fn assert_same_behaviour(file: &Path) {
+    let path = file.to_str().unwrap();
+    let plain = hammer(&["run", path]);
+    let optimized = hammer(&["run", "-O", path]);
+    assert_eq!(plain.status.code(), optimized.status.code(), "{path}");
+    assert_eq!(stdout(&plain), stdout(&optimized), "{path}");
+    assert_eq!(stderr(&plain), stderr(&optimized), "{path}");
+}
+
+fn code_size(file: &Path, optimize: bool) -> u64 {
+    let (args, out) = match optimize {
+        true => (&["compile", "-O"][..], file.with_extension("opt.hbc")),
+        false => (&["compile"][..], file.with_extension("hbc")),
+    };
+    let path = file.to_str().unwrap();
+    let output = hammer(&[args, &[path, out.to_str().unwrap()]].concat());
+    assert!(output.status.success(), "{path}");
+    let size = fs::metadata(&out).expect("нет файла байткода").len();
+    let _ = fs::remove_file(out);
+    size
+}
+
+#[test]
+fn examples_behave_the_same_when_optimized() {
+    let examples = examples();
+    assert!(!examples.is_empty());
+    for example in examples {
+        if is_failing_example(&example) {
+            let output = hammer(&["run", "-O", example.to_str().unwrap()]);
+            assert_eq!(output.status.code(), Some(5), "{}", example.display());
+        }
+        assert_same_behaviour(&example);
+    }
+}
+
+#[test]
+fn optimized_program_is_smaller_and_behaves_the_same() {
+    let file = source_file(
+        "peephole.hmr",
+        r#"
+fn unused(a) {
+    return a;
+}
+fn sign(a) {
+    if a > 0 {
+        return 1;
+    } else {
+        return -1;
+    }
+    println("недостижимо");
+}
+let x = 5;
+x = x;
+let y = --x;
+let t = 0;
+for i in 0..4 {
+    if i == 1 {
+        if t == 0 { t = t + 1; } else { t = t + 2; }
+    } else {
+        t = t + 10;
+    }
+}
+println(sign(y) + t);
+println(-x + "s");
+"#,
+    );
+    assert_same_behaviour(&file);
+    assert!(code_size(&file, true) < code_size(&file, false));
+    let _ = fs::remove_file(file);
+}
+
+#[test]
+fn call_stack_is_preserved_when_optimized() {
+    let file = source_file(
+        "call-stack.hmr",
+        "fn never() {\n    return 1;\n}\nfn div(a, b) {\n    return a / b;\n}\n\
+         fn zero(a) {\n    return div(a, a - a);\n}\nprintln(zero(3));\n",
+    );
+    assert_same_behaviour(&file);
+    let output = hammer(&["run", "-O", file.to_str().unwrap()]);
+    assert!(stderr(&output).contains("в функции div"));
+    let _ = fs::remove_file(file);
+}
+
+#[test]
+fn double_negation_of_a_string_still_fails() {
+    let file = source_file("double-neg.hmr", "let s = \"a\";\nprintln(--s);\n");
+    assert_same_behaviour(&file);
+    let output = hammer(&["run", "-O", file.to_str().unwrap()]);
+    assert_eq!(output.status.code(), Some(7));
+    assert!(stderr(&output).contains("[H0409]"), "{}", stderr(&output));
+    let _ = fs::remove_file(file);
+}
@@ -0,0 +1,215 @@
+// Байткод проверяется перед запуском: неверный файл дает ошибку H05xx
+// с кодом завершения 6, а скомпилированные программы проверку проходят
+mod common;
+
+use common::{examples, hammer, is_failing_example, stderr, stdout, temp_path};
+use hammer::Value;
+use std::fs;
+use std::process::Output;
+
+// Файл с секциями кода, констант и, если есть, отладочной информации.
+// Формат описан в docs/bytecode.txt
+fn bytecode_file(code: Vec<u8>, consts: Vec<Value>, debug: Option<Vec<u8>>) -> Vec<u8> {
+    let mut const_table = vec![];
+    for value in consts {
+        let (kind, data) = match value {
+            Value::Number(n) => (0x00, n.to_le_bytes().to_vec()),
+            Value::Bool(b) => (0x01, vec![b as u8]),
+            Value::Nil => (0x02, vec![]),
+            Value::Str(s) => (0x03, s.as_bytes().to_vec()),
+        };
+        const_table.push(kind);
+        const_table.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        const_table.extend_from_slice(&data);
+    }
+    let mut sections = vec![(0x01, code), (0x02, const_table)];
+    sections.extend(debug.map(|debug| (0x03, debug)));
+    let mut bytes = b"HMR\0".to_vec();
+    bytes.extend_from_slice(&2u16.to_le_bytes());
+    bytes.extend_from_slice(&(sections.len() as u16).to_le_bytes());
+    for (kind, data) in sections {
+        bytes.push(kind);
+        bytes.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(&data);
+    }
+    bytes
+}
+
+// Отладочная информация с участками кода для команд, начиная со смещения 0,
+// и адресами функций. Имя файла указывает на существующий файл, чтобы
+// при ошибке выполнения выводился фрагмент исходного кода
+fn debug_section(spans: &[[u32; 4]], functions: &[u32]) -> Vec<u8> {
+    let file = concat!(env!("CARGO_MANIFEST_DIR"), "/Cargo.toml");
+    let mut bytes = (file.len() as u16).to_le_bytes().to_vec();
+    bytes.extend_from_slice(file.as_bytes());
+    bytes.extend_from_slice(&(spans.len() as u32).to_le_bytes());
+    for span in spans {
+        bytes.extend_from_slice(&0u32.to_le_bytes());
+        for n in span {
+            bytes.extend_from_slice(&n.to_le_bytes());
+        }
+    }
+    bytes.extend_from_slice(&(functions.len() as u32).to_le_bytes());
+    for address in functions {
+        bytes.extend_from_slice(&address.to_le_bytes());
+        bytes.extend_from_slice(&1u16.to_le_bytes());
+        bytes.push(b'f');
+    }
+    bytes
+}
+
+fn run_bytecode(name: &str, bytes: Vec<u8>) -> Output {
+    let path = temp_path(&format!("{name}.hbc"));
+    fs::write(&path, bytes).expect("не удалось записать временный файл");
+    let output = hammer(&["run", "-b", path.to_str().unwrap()]);
+    let _ = fs::remove_file(path);
+    output
+}
+
+fn assert_rejected(name: &str, code: Vec<u8>, consts: Vec<Value>, error: &str) {
+    assert_file_rejected(name, bytecode_file(code, consts, None), error);
+}
+
+fn assert_file_rejected(name: &str, bytes: Vec<u8>, error: &str) {
+    let output = run_bytecode(name, bytes);
+    let stderr = stderr(&output);
+    assert_eq!(output.status.code(), Some(6), "{name}: {stderr}");
+    assert!(stderr.contains(&format!("[{error}]")), "{name}: {stderr}");
+}
+
+#[test]
+fn compiled_examples_pass_verification() {
+    for example in examples() {
+        let source = example.to_str().unwrap();
+        for args in [&["compile"][..], &["compile", "-O"][..]] {
+            let out = temp_path("verified.hbc");
+            let out = out.to_str().unwrap();
+            let compiled = hammer(&[args, &[source, out]].concat());
+            if is_failing_example(&example) {
+                assert_eq!(compiled.status.code(), Some(5), "{source} {args:?}");
+                continue;
+            }
+            assert!(
+                compiled.status.success(),
+                "{source} {args:?}: {}",
+                stderr(&compiled)
+            );
+            let from_source = hammer(&[&["run"], &args[1..], &[source]].concat());
+            let from_bytecode = hammer(&["run", "-b", out]);
+            let _ = fs::remove_file(out);
+            assert_eq!(
+                from_source.status.code(),
+                from_bytecode.status.code(),
+                "{source} {args:?}"
+            );
+            assert_eq!(
+                stdout(&from_source),
+                stdout(&from_bytecode),
+                "{source} {args:?}"
+            );
+        }
+    }
+}
+
+#[test]
+fn malformed_code_is_rejected() {
+    let number = || vec![Value::Number(1.0)];
+    // Коды 0x07 и 0x20 не заняты ни одной командой
+    for opcode in [0x07, 0x20] {
+        let name = format!("unknown-opcode-{opcode:#04x}");
+        assert_rejected(&name, vec![opcode, 0x00, 0x00, 0xff], vec![], "H0503");
+    }
+    assert_rejected("truncated", vec![0x01, 0x00], number(), "H0504");
+    // JMP +2 ведет внутрь PUSH
+    assert_rejected(
+        "jump-inside",
+        vec![0x0e, 0x02, 0x00, 0x01, 0x00, 0x00, 0xff],
+        number(),
+        "H0511",
+    );
+    // JBACK на 5 байт назад от начала кода
+    assert_rejected("jump-before-start", vec![0x10, 0x05, 0x00], vec![], "H0511");
+    assert_rejected(
+        "call-outside",
+        vec![0x16, 9, 0, 0, 0, 0xff],
+        vec![],
+        "H0511",
+    );
+    assert_rejected("bad-const", vec![0x01, 0x01, 0x00, 0xff], number(), "H0512");
+    assert_rejected("bad-builtin", vec![0x11, 0x40, 0x00, 0xff], vec![], "H0513");
+    assert_rejected("bad-host", vec![0x1a, 0x00, 0x00, 0xff], vec![], "H0514");
+    assert_rejected("no-hlt", vec![0x01, 0x00, 0x00, 0x15], number(), "H0517");
+    // Деление на 0 с отладочной информацией: PUSH 0; PUSH 1; DIV; HLT
+    let division = |debug| {
+        bytecode_file(
+            vec![0x01, 0x00, 0x00, 0x01, 0x01, 0x00, 0x05, 0xff],
+            vec![Value::Number(1.0), Value::Number(0.0)],
+            Some(debug),
+        )
+    };
+    let output = run_bytecode("debug", division(debug_section(&[[1, 1, 1, 5]], &[])));
+    assert_eq!(output.status.code(), Some(7), "{}", stderr(&output));
+    // Строки и столбцы нумеруются с 1, конец участка не раньше начала
+    for span in [[0, 0, 0, 0], [1, 0, 1, 5], [2, 3, 1, 9], [1, 5, 1, 4]] {
+        let name = format!("bad-span-{}-{}-{}-{}", span[0], span[1], span[2], span[3]);
+        let bytes = division(debug_section(&[span], &[]));
+        assert_file_rejected(&name, bytes, "H0502");
+    }
+    // Функция начинается внутри PUSH или за концом кода
+    for address in [1, 8] {
+        let bytes = division(debug_section(&[[1, 1, 1, 5]], &[address]));
+        assert_file_rejected(&format!("bad-function-{address}"), bytes, "H0520");
+    }
+    // PUSH 0; LIV 0xffffffff; HLT - VM не должна выделять память под 2^32 переменных
+    assert_rejected(
+        "huge-variable",
+        vec![0x01, 0x00, 0x00, 0x12, 0xff, 0xff, 0xff, 0xff, 0xff],
+        number(),
+        "H0519",
+    );
+}
+
+#[test]
+fn inconsistent_stack_is_rejected() {
+    let condition = || vec![Value::Bool(true)];
+    // ADD на пустом стеке
+    assert_rejected("underflow", vec![0x02, 0xff], vec![], "H0516");
+    // Одна ветка JF кладет значение на стек, другая - нет:
+    //     PUSH 0; JF +6; PUSH 0; HLT
+    assert_rejected(
+        "mismatch",
+        vec![0x01, 0x00, 0x00, 0x0f, 0x06, 0x00, 0x01, 0x00, 0x00, 0xff],
+        condition(),
+        "H0515",
+    );
+    // Цикл, на каждой итерации которого стек растет:
+    //     PUSH 0; JBACK 0
+    assert_rejected(
+        "growing-loop",
+        vec![0x01, 0x00, 0x00, 0x10, 0x03, 0x00],
+        condition(),
+        "H0515",
+    );
+    // Функция забирает со стека вызывающего больше, чем возвращает:
+    //     CALL f; HLT; f: LIV 0; PUSH 0; RET
+    assert_rejected(
+        "call-underflow",
+        vec![
+            0x16, 0x06, 0x00, 0x00, 0x00, 0xff, 0x12, 0x00, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00,
+            0x17,
+        ],
+        vec![Value::Number(1.0)],
+        "H0516",
+    );
+    // Рекурсивный вызов каждый раз снимает со стека еще одно значение:
+    //     HLT; f: POP; PUSH 0; JF +8; CALL f; PUSH 0; RET
+    assert_rejected(
+        "recursive-underflow",
+        vec![
+            0xff, 0x15, 0x01, 0x00, 0x00, 0x0f, 0x08, 0x00, 0x16, 0x01, 0x00, 0x00, 0x00, 0x01,
+            0x00, 0x00, 0x17,
+        ],
+        vec![Value::Bool(false)],
+        "H0516",
+    );
+}